@@ -0,0 +1,191 @@
+use html_parse::HTMLEmbed;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::str;
+use std::thread;
+
+/// Why an embed's command failed, along with anything it wrote to
+/// stderr
+#[derive(Debug)]
+pub struct EmbedError {
+    pub reason: String,
+    pub stderr: String,
+}
+
+impl fmt::Display for EmbedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.reason)?;
+        for line in self.stderr.trim_end().lines() {
+            write!(f, "\n  | {}", line)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for EmbedError {}
+
+impl From<io::Error> for EmbedError {
+    fn from(err: io::Error) -> Self {
+        EmbedError {
+            reason: format!("Can't run command: {}", err),
+            stderr: String::new(),
+        }
+    }
+}
+
+/// Run an embed's command, returning its trimmed output
+///
+/// Fails if the command can't be run, exits with a non-zero status
+/// or writes output which isn't UTF-8. Stderr is passed through when
+/// the command succeeds.
+///
+/// Runs in the working directory if given and not empty, or else the
+/// current one.
+/// Input is written from another thread while output is read, so
+/// commands streaming large inputs through don't block on a full
+/// pipe. Commands are free to leave their input unread.
+///
+pub fn exec_embed(request: &HTMLEmbed, working_dir: Option<&Path>) -> Result<String, EmbedError> {
+    let HTMLEmbed { command, input, .. } = request;
+    let stdin = if input.is_some() {
+        Stdio::piped()
+    } else {
+        Stdio::null()
+    };
+
+    let mut process = Command::new("sh");
+    process
+        .arg("-c")
+        .arg(command.as_ref())
+        .stdin(stdin)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(working_dir) = working_dir.filter(|dir| !dir.as_os_str().is_empty()) {
+        process.current_dir(working_dir);
+    }
+    let mut child = process.spawn()?;
+
+    let writer = match (child.stdin.take(), input) {
+        (Some(mut child_stdin), Some(input_text)) => {
+            let input_text = input_text.clone().into_owned();
+            Some(thread::spawn(move || {
+                child_stdin.write_all(input_text.as_bytes())
+            }))
+        }
+        _ => None,
+    };
+    let output = child.wait_with_output()?;
+    if let Some(writer) = writer {
+        match writer.join() {
+            Ok(Err(err)) if err.kind() != io::ErrorKind::BrokenPipe => return Err(err.into()),
+            _ => {}
+        }
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    if !output.status.success() {
+        return Err(EmbedError {
+            reason: output.status.to_string(),
+            stderr,
+        });
+    }
+    let text = match str::from_utf8(&output.stdout) {
+        Ok(text) => text,
+        Err(err) => {
+            return Err(EmbedError {
+                reason: format!("Output isn't valid UTF-8: {}", err),
+                stderr,
+            })
+        }
+    };
+    io::stderr().write_all(stderr.as_bytes())?;
+
+    Ok(text.trim().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use html_parse::{iter_embeds, HTMLPartOrEmbed, HTMLTag, HTMLTagKind, Span, Tokenizer};
+
+    fn embed(command: &str, input: Option<&str>) -> HTMLEmbed<'static> {
+        HTMLEmbed {
+            command: command.to_owned().into(),
+            input: input.map(|input| input.to_owned().into()),
+            tag: HTMLTag {
+                kind: HTMLTagKind::Void,
+                name: "run".into(),
+                attributes: Vec::new(),
+                span: Span::default(),
+            },
+            span: Span::default(),
+        }
+    }
+
+    #[test]
+    fn exec_with_input() {
+        let output = exec_embed(&embed("tr a-z A-Z", Some("hi\n")), None).unwrap();
+
+        assert_eq!(output, "HI");
+    }
+
+    #[test]
+    fn exec_entity_encoded_command() {
+        let html = "<run command=\"echo a &amp;&amp; echo &quot;b&gt;c&quot;\"/>";
+        let embed = match iter_embeds(Tokenizer::new(html)).next() {
+            Some(HTMLPartOrEmbed::Embed(embed)) => embed,
+            part => panic!("Expected an embed, got {:?}", part),
+        };
+
+        assert_eq!(exec_embed(&embed, None).unwrap(), "a\nb>c");
+    }
+
+    #[test]
+    fn exec_large_input() {
+        let input = "x".repeat(1 << 20);
+        let output = exec_embed(&embed("cat; echo done >&2", Some(&input)), None).unwrap();
+
+        assert_eq!(output.len(), input.len());
+    }
+
+    #[test]
+    fn exec_ignoring_input() {
+        let input = "x".repeat(1 << 20);
+
+        assert_eq!(
+            exec_embed(&embed("echo ok", Some(&input)), None).unwrap(),
+            "ok"
+        );
+    }
+
+    #[test]
+    fn exec_in_working_dir() {
+        let output = exec_embed(&embed("pwd", None), Some(Path::new("/"))).unwrap();
+
+        assert_eq!(output, "/");
+    }
+
+    #[test]
+    fn exec_in_empty_working_dir() {
+        let output = exec_embed(&embed("echo ok", None), Some(Path::new(""))).unwrap();
+
+        assert_eq!(output, "ok");
+    }
+
+    #[test]
+    fn exec_failure() {
+        let err = exec_embed(&embed("echo oops >&2; exit 3", None), None).unwrap_err();
+
+        assert_eq!(err.to_string(), "exit status: 3\n  | oops");
+    }
+
+    #[test]
+    fn exec_invalid_utf8() {
+        let err = exec_embed(&embed("printf '\\377'", None), None).unwrap_err();
+
+        assert!(err.reason.starts_with("Output isn't valid UTF-8"));
+    }
+}
@@ -24,7 +24,7 @@ impl<'a> Arguments<'a> {
         Arguments { matches }
     }
 
-    pub fn output_file_path(self: &Self) -> &Path {
+    pub fn output_file_path(&self) -> &Path {
         let path: &Path = self
             .matches
             .value_of(OUTPUT_FILE_PATH_ARG)
@@ -1,13 +1,13 @@
-use crate::file_helpers::{read_file, write_file};
+use crate::file_helpers::OutputDir;
 use async_std::path::Path;
 
 /// Add file to output directory
-pub async fn add_file<P: AsRef<Path>, Q: AsRef<Path>>(
-    output_dir: P,
-    input_file_path: Q,
+pub async fn add_file<P: AsRef<Path>>(
+    output: &OutputDir,
+    input_file_path: P,
+    contents: &[u8],
 ) -> anyhow::Result<()> {
-    let contents = read_file(&input_file_path).await?;
-    write_file(output_dir, input_file_path, contents).await?;
+    output.write_file(input_file_path, contents).await?;
 
     Ok(())
 }
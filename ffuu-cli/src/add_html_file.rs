@@ -1,54 +1,236 @@
-use crate::add_file::add_file;
-use crate::file_helpers::{read_file, write_file};
+use crate::add_site::FileKind;
+use crate::embed_outputs::EmbedOutputs;
+use crate::file_helpers::{relative_file_path, relative_path, OutputDir};
 use anyhow::anyhow;
-use async_std::path::Path;
-use html_parse::{parse_all_parts, HTMLPart, URI_HTML_ATTRIBUTES};
+use async_std::path::{Path, PathBuf};
+use async_std::task::spawn_blocking;
+use html_parse::{
+    decode_attribute_value, escape_attribute_value, iter_embeds, minify_html, parse_html_lenient,
+    FormatOptions, HTMLFormatter, HTMLPart, HTMLPartOrEmbed, HTMLTag, Span, Tokenizer,
+    URI_HTML_ATTRIBUTES,
+};
 use std::str;
-use url::Url;
 
-/// Add file to output directory
-pub async fn add_html_file<P: AsRef<Path>, Q: AsRef<Path>>(
-    output_dir: P,
-    input_file_path: Q,
-) -> anyhow::Result<()> {
+/// Add HTML file to output directory
+///
+/// Embeds are executed, and the HTML is otherwise copied as is,
+/// unless minifying. Returns the files it references by relative
+/// path, for the caller to add.
+///
+pub async fn add_html_file<P: AsRef<Path>>(
+    output: &OutputDir,
+    input_file_path: P,
+    contents: &[u8],
+    minify: bool,
+    embeds: &mut EmbedOutputs,
+) -> anyhow::Result<Vec<Dependency>> {
+    let html = str::from_utf8(contents)?.to_owned();
+    let (output_html, dependencies) =
+        spawn_build_html(input_file_path.as_ref(), html, minify, embeds).await?;
+    output.write_file(input_file_path, output_html).await?;
+
+    Ok(dependencies)
+}
+
+/// Build HTML on a thread where blocking is fine
+///
+/// Embeds block on their commands, which would otherwise hold up
+/// other files' builds, and the server when serving.
+///
+pub async fn spawn_build_html(
+    input_file_path: &Path,
+    html: String,
+    minify: bool,
+    embeds: &mut EmbedOutputs,
+) -> anyhow::Result<(String, Vec<Dependency>)> {
+    let input_file_path = input_file_path.to_path_buf();
+    let mut moved_embeds = std::mem::take(embeds);
+    let (result, moved_embeds) = spawn_blocking(move || {
+        let result = build_html(&input_file_path, &html, minify, &mut moved_embeds);
+        (result, moved_embeds)
+    })
+    .await;
+    *embeds = moved_embeds;
+
+    result
+}
+
+/// Execute embeds in HTML from an input file
+///
+/// Returns the output HTML along with the files it references by
+/// relative path, including those referenced by embed output. Links
+/// to Markdown files point to their HTML output instead.
+///
+fn build_html(
+    input_file_path: &Path,
+    html: &str,
+    minify: bool,
+    embeds: &mut EmbedOutputs,
+) -> anyhow::Result<(String, Vec<Dependency>)> {
     let input_dir = input_file_path
-        .as_ref()
         .parent()
         .ok_or(anyhow!("Can't get parent of directory"))?;
-    let contents = read_file(&input_file_path).await?;
-    let html = str::from_utf8(&contents)?;
-    let relative_paths = find_relative_paths(html)?;
-    for rp in relative_paths {
-        add_file(&output_dir, input_dir.join(rp)).await?;
+    let (parsed_parts, warnings) = parse_html_lenient(html);
+    for warning in warnings {
+        eprintln!("Warning: {}", warning.with_file(input_file_path));
+    }
+
+    let mut formatter = HTMLFormatter::with_options(FormatOptions::Verbatim(html));
+    let mut built = BuiltHTML::default();
+    for part_or_embed in iter_embeds(parsed_parts) {
+        match part_or_embed {
+            HTMLPartOrEmbed::Part(part) => built.push(&mut formatter, part),
+            HTMLPartOrEmbed::Embed(embed) => {
+                let embed_output = embeds
+                    .get_or_exec(&embed, input_dir.as_ref())
+                    .map_err(|err| anyhow!("Embed `{}` failed: {}", embed.command, err))?;
+                let mut embed_formatter =
+                    HTMLFormatter::with_options(FormatOptions::Verbatim(&embed_output));
+                for embed_part in Tokenizer::new(&embed_output) {
+                    built.push(&mut embed_formatter, embed_part.into_owned());
+                }
+            }
+        }
+    }
+
+    let output_html = if minify {
+        minify_html(&built.parts)
+    } else {
+        built.output_html
+    };
+    let dependencies = built
+        .relative_paths
+        .into_iter()
+        .map(|(attribute, rp)| Dependency {
+            path: input_dir.join(rp),
+            referrer: Some((input_file_path.to_path_buf(), attribute)),
+        })
+        .collect();
+
+    Ok((output_html, dependencies))
+}
+
+/// HTML built so far, part by part
+#[derive(Default)]
+struct BuiltHTML<'a> {
+    output_html: String,
+    parts: Vec<HTMLPart<'a>>,
+    relative_paths: Vec<(String, String)>,
+}
+
+impl<'a> BuiltHTML<'a> {
+    /// Add a part, linking to the HTML output of any Markdown sources
+    fn push(&mut self, formatter: &mut HTMLFormatter, part: HTMLPart<'a>) {
+        self.relative_paths
+            .extend(find_relative_paths(std::slice::from_ref(&part)));
+
+        let part = match part {
+            HTMLPart::Tag(tag) => HTMLPart::Tag(link_markdown_output(tag)),
+            part => part,
+        };
+        self.output_html.push_str(&formatter.format_part(&part));
+        self.parts.push(part);
+    }
+}
+
+/// Point `href`s at Markdown sources to the HTML they're rendered to
+///
+/// Keeps any query or fragment. A rewritten tag loses its span, so
+/// it's formatted anew rather than copied from the source.
+///
+fn link_markdown_output(mut tag: HTMLTag<'_>) -> HTMLTag<'_> {
+    let mut is_rewritten = false;
+
+    for (name, value) in &mut tag.attributes {
+        let raw_value = match value {
+            Some(raw_value) if name.eq_ignore_ascii_case("href") => raw_value,
+            _ => continue,
+        };
+        let uri = decode_attribute_value(raw_value);
+        let is_markdown = relative_file_path(&uri)
+            .is_some_and(|path| FileKind::of(Path::new(&path)) == FileKind::MarkdownPage);
+        let rp = match relative_path(&uri) {
+            Some(rp) if is_markdown => rp,
+            _ => continue,
+        };
+
+        let html_path = Path::new(rp).with_extension("html");
+        let html_uri = format!("{}{}", html_path.display(), &uri[rp.len()..]);
+        *value = Some(escape_attribute_value(&html_uri).into_owned().into());
+        is_rewritten = true;
+    }
+    if is_rewritten {
+        tag.span = Span::default();
     }
 
-    write_file(output_dir, input_file_path, contents).await?;
+    tag
+}
 
-    Ok(())
+/// File referenced by relative path
+pub struct Dependency {
+    pub path: PathBuf,
+    /// Referencing file and attribute, unless it's a root file
+    pub referrer: Option<(PathBuf, String)>,
 }
 
-fn find_relative_paths(html: &str) -> anyhow::Result<Vec<&str>> {
+/// Relative paths referenced by URI attributes
+///
+/// Along with the attribute names. Percent-decoded, without any
+/// query or fragment, so they name files.
+///
+fn find_relative_paths(parts: &[HTMLPart<'_>]) -> Vec<(String, String)> {
     let mut rps = Vec::new();
-    let parts = match parse_all_parts(html) {
-        Ok((_, parts)) => Ok(parts),
-        Err(err) => Err(anyhow!("Can't parse HTML: {}", err)),
-    }?;
 
     for p in parts {
         if let HTMLPart::Tag(tag) = p {
-            for attr in tag.attributes {
-                if let (name, Some(value)) = attr {
-                    let is_uri_attribute = URI_HTML_ATTRIBUTES.contains(name);
-                    let is_absolute_url = is_uri_attribute && Url::parse(value).is_ok();
-                    let is_relative_path = !is_absolute_url && Path::new(value).is_relative();
-
-                    if is_uri_attribute && !is_absolute_url && is_relative_path {
-                        rps.push(value);
+            for attr in &tag.attributes {
+                if let (name, Some(raw_value)) = attr {
+                    if !URI_HTML_ATTRIBUTES.contains(name.as_ref()) {
+                        continue;
+                    }
+                    if let Some(rp) = relative_file_path(&decode_attribute_value(raw_value)) {
+                        rps.push((name.to_string(), rp));
                     }
                 }
             }
         }
     }
 
-    Ok(rps)
+    rps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use html_parse::HTMLTagKind;
+
+    fn link(href: &str) -> HTMLTag<'_> {
+        HTMLTag {
+            kind: HTMLTagKind::Open,
+            name: "a".into(),
+            attributes: vec![("href".into(), Some(href.into()))],
+            span: Span::new(0, 10),
+        }
+    }
+
+    #[test]
+    fn link_encoded_markdown_output() {
+        let tag = link_markdown_output(link("./my%20post.md#intro"));
+
+        assert_eq!(
+            tag.attribute_value("href"),
+            Some("./my%20post.html#intro".into())
+        );
+        assert_eq!(tag.span, Span::default());
+    }
+
+    #[test]
+    fn find_encoded_relative_paths() {
+        let parts = vec![HTMLPart::Tag(link("./my%20post.md?a=1"))];
+
+        assert_eq!(
+            find_relative_paths(&parts),
+            vec![("href".to_string(), "./my post.md".to_string())]
+        );
+    }
 }
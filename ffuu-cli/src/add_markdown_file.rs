@@ -0,0 +1,32 @@
+use crate::add_html_file::{spawn_build_html, Dependency};
+use crate::embed_outputs::EmbedOutputs;
+use crate::file_helpers::OutputDir;
+use async_std::path::Path;
+use pulldown_cmark::{html, Parser};
+use std::str;
+
+/// Add Markdown file to output directory
+///
+/// Rendered to HTML next to where the Markdown would go, with the
+/// `.html` extension, then built like any other HTML file. Returns
+/// the files it references by relative path, for the caller to add.
+///
+pub async fn add_markdown_file<P: AsRef<Path>>(
+    output: &OutputDir,
+    input_file_path: P,
+    contents: &[u8],
+    minify: bool,
+    embeds: &mut EmbedOutputs,
+) -> anyhow::Result<Vec<Dependency>> {
+    let markdown = str::from_utf8(contents)?;
+
+    let mut html = String::new();
+    html::push_html(&mut html, Parser::new(markdown));
+    let (output_html, dependencies) =
+        spawn_build_html(input_file_path.as_ref(), html, minify, embeds).await?;
+    output
+        .write_file_with_extension(input_file_path, "html", output_html)
+        .await?;
+
+    Ok(dependencies)
+}
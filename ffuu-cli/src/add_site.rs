@@ -0,0 +1,494 @@
+use crate::add_file::add_file;
+use crate::add_html_file::{add_html_file, Dependency};
+use crate::add_markdown_file::add_markdown_file;
+use crate::digest::Digest;
+use crate::embed_outputs::EmbedOutputs;
+use crate::file_helpers::{read_file, OutputDir};
+use crate::manifest::{FileRecord, Manifest};
+use anyhow::anyhow;
+use async_std::fs::{canonicalize, read_dir, remove_file};
+use async_std::io;
+use async_std::path::{Path, PathBuf};
+use async_std::prelude::StreamExt;
+use async_std::task::spawn;
+use futures::future;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Site built from an input
+///
+/// The input is either a root file, or a directory whose HTML and
+/// Markdown files are all root files. Output mirrors the directory
+/// structure under the input directory, or the root file's directory.
+///
+/// Files which haven't changed since the build recorded by the
+/// manifest are skipped.
+///
+pub struct Site {
+    input_path: PathBuf,
+    is_input_dir: bool,
+    output: OutputDir,
+    /// Canonical paths of files already added
+    visited: Mutex<HashSet<PathBuf>>,
+    /// Every file referenced so far, whether it exists or not
+    referenced: Mutex<HashSet<PathBuf>>,
+    /// Pages by the files their embeds declare as inputs
+    embed_inputs: Mutex<HashMap<PathBuf, HashSet<PathBuf>>>,
+    errors: Mutex<Vec<BuildError>>,
+    /// Records of the latest build of each file
+    manifest: Mutex<Manifest>,
+    minify: bool,
+    keep_going: bool,
+}
+
+impl Site {
+    pub async fn new<P: AsRef<Path>, Q: AsRef<Path>>(
+        output_dir: P,
+        input_path: Q,
+        manifest: Manifest,
+        keep_going: bool,
+    ) -> anyhow::Result<Self> {
+        let input_path = input_path.as_ref();
+        let is_input_dir = input_path.is_dir().await;
+        let root_dir = if is_input_dir {
+            input_path
+        } else {
+            input_path
+                .parent()
+                .ok_or(anyhow!("Can't get parent of directory"))?
+        };
+
+        Ok(Site {
+            input_path: input_path.to_path_buf(),
+            is_input_dir,
+            output: OutputDir::new(output_dir, root_dir),
+            visited: Mutex::new(HashSet::new()),
+            referenced: Mutex::new(HashSet::new()),
+            embed_inputs: Mutex::new(HashMap::new()),
+            errors: Mutex::new(Vec::new()),
+            minify: manifest.minify,
+            manifest: Mutex::new(manifest),
+            keep_going,
+        })
+    }
+
+    /// Add every file reachable from the input to output directory
+    ///
+    /// Each root file is crawled concurrently, skipping files which
+    /// were already added. The first failure stops the build, unless
+    /// keeping going, in which case everything else is still built.
+    /// Either way, every failure is reported once the crawls are done.
+    ///
+    /// Once everything is built, outputs of files which are no longer
+    /// part of the site are deleted.
+    ///
+    pub async fn build(self: &Arc<Self>) -> anyhow::Result<()> {
+        let root_file_paths = self.root_file_paths().await?;
+        let result = self.crawl_all(root_file_paths).await;
+        if result.is_ok() {
+            self.delete_stale_outputs().await?;
+        }
+        self.save_manifest().await?;
+
+        result
+    }
+
+    /// Add changed files again, along with anything they newly reference
+    ///
+    /// A changed input directory adds any new root files, and a
+    /// changed embed input adds the pages whose embeds declare it.
+    /// Outputs of files which are no longer part of the site are
+    /// deleted afterwards, like after a build.
+    ///
+    pub async fn rebuild(self: &Arc<Self>, changed_paths: Vec<PathBuf>) -> anyhow::Result<()> {
+        let mut file_paths = Vec::new();
+        for path in changed_paths {
+            if self.is_input_dir && path == self.input_path {
+                file_paths.extend(find_pages(&self.input_path).await?);
+                continue;
+            }
+            let mut affected_paths: Vec<PathBuf> =
+                match self.embed_inputs.lock().unwrap().get(&path) {
+                    Some(pages) => pages.iter().cloned().collect(),
+                    None => Vec::new(),
+                };
+            if self.referenced.lock().unwrap().contains(&path) {
+                affected_paths.push(path);
+            }
+            for affected_path in affected_paths {
+                if let Ok(canonical_path) = canonicalize(&affected_path).await {
+                    self.visited.lock().unwrap().remove(&canonical_path);
+                }
+                file_paths.push(affected_path);
+            }
+        }
+
+        let result = self.crawl_all(file_paths).await;
+        if result.is_ok() {
+            self.delete_stale_outputs().await?;
+        }
+        self.save_manifest().await?;
+
+        result
+    }
+
+    /// Paths whose changes affect the site
+    ///
+    /// Every file referenced so far, whether it exists or not, every
+    /// file declared as an embed input, and the input directory, if
+    /// any.
+    ///
+    pub fn watched_paths(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self.referenced.lock().unwrap().iter().cloned().collect();
+        paths.extend(self.embed_inputs.lock().unwrap().keys().cloned());
+        if self.is_input_dir {
+            paths.push(self.input_path.clone());
+        }
+
+        paths
+    }
+
+    async fn crawl_all(self: &Arc<Self>, file_paths: Vec<PathBuf>) -> anyhow::Result<()> {
+        let tasks: Vec<_> = file_paths
+            .into_iter()
+            .map(|path| spawn(crawl(self.clone(), path)))
+            .collect();
+        future::join_all(tasks).await;
+
+        let errors: Vec<BuildError> = self.errors.lock().unwrap().drain(..).collect();
+        if errors.is_empty() {
+            return Ok(());
+        }
+        for err in &errors {
+            eprintln!("Error: {}", err);
+        }
+        if !self.keep_going {
+            eprintln!("Stopped at the first failure, use --keep-going to build the rest");
+        }
+
+        Err(FailedFiles(errors.len()).into())
+    }
+
+    /// Root files, found again in case the input directory changed
+    async fn root_file_paths(&self) -> anyhow::Result<Vec<PathBuf>> {
+        if self.is_input_dir {
+            find_pages(&self.input_path).await
+        } else {
+            Ok(vec![self.input_path.clone()])
+        }
+    }
+
+    /// Root relative paths of files reachable from the root files,
+    /// going by the dependencies recorded in the manifest
+    async fn reachable_files(&self) -> anyhow::Result<HashSet<PathBuf>> {
+        let root_file_paths = self.root_file_paths().await?;
+        let mut queue: Vec<PathBuf> = root_file_paths
+            .iter()
+            .filter_map(|path| self.output.relative_path(path).ok())
+            .collect();
+
+        let manifest = self.manifest.lock().unwrap();
+        let mut reachable = HashSet::new();
+        while let Some(path) = queue.pop() {
+            if let Some(record) = manifest.files.get(&path) {
+                let dependency_paths = record.dependencies.iter().filter_map(|(_, dependency)| {
+                    let dependency_path = self.output.root_dir().join(dependency);
+                    self.output.relative_path(dependency_path).ok()
+                });
+                queue.extend(dependency_paths);
+            }
+            reachable.insert(path);
+        }
+
+        Ok(reachable)
+    }
+
+    /// Delete outputs of files which are no longer reachable
+    ///
+    /// They're forgotten as visited too, so referencing them again
+    /// adds them back.
+    ///
+    async fn delete_stale_outputs(&self) -> anyhow::Result<()> {
+        let reachable = self.reachable_files().await?;
+        let stale_records: Vec<(PathBuf, FileRecord)> = {
+            let mut manifest = self.manifest.lock().unwrap();
+            let stale_paths: Vec<PathBuf> = manifest
+                .files
+                .keys()
+                .filter(|path| !reachable.contains(*path))
+                .cloned()
+                .collect();
+            stale_paths
+                .iter()
+                .filter_map(|path| Some((path.clone(), manifest.files.remove(path)?)))
+                .collect()
+        };
+
+        for (path, record) in stale_records {
+            self.output.release(&path);
+            if let Ok(canonical_path) = canonicalize(self.output.root_dir().join(&path)).await {
+                self.visited.lock().unwrap().remove(&canonical_path);
+            }
+            for output in record.outputs {
+                let output_path = self.output.output_dir().join(output);
+                if self.output.is_claimed(&output_path) {
+                    continue;
+                }
+                match remove_file(&output_path).await {
+                    Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn save_manifest(&self) -> anyhow::Result<()> {
+        let text = self.manifest.lock().unwrap().to_text();
+        Manifest::save(self.output.output_dir(), text).await
+    }
+
+    /// Keep track of the embed inputs of a page, replacing those
+    /// from its last build
+    fn record_embed_inputs(&self, page_path: &Path, input_paths: Vec<PathBuf>) {
+        let mut embed_inputs = self.embed_inputs.lock().unwrap();
+        for pages in embed_inputs.values_mut() {
+            pages.remove(page_path);
+        }
+        embed_inputs.retain(|_, pages| !pages.is_empty());
+        for input_path in input_paths {
+            embed_inputs
+                .entry(input_path)
+                .or_default()
+                .insert(page_path.to_path_buf());
+        }
+    }
+
+    /// Whether the build should stop for a failure
+    fn is_stopped(&self) -> bool {
+        !self.keep_going && !self.errors.lock().unwrap().is_empty()
+    }
+}
+
+/// Failure of a build which added everything it could, apart from
+/// some number of files
+///
+/// Unlike other build errors, the output is still worth keeping when
+/// keeping going.
+///
+#[derive(Debug)]
+pub struct FailedFiles(usize);
+
+impl fmt::Display for FailedFiles {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let noun = if self.0 == 1 { "file" } else { "files" };
+        write!(f, "{} {} failed to build", self.0, noun)
+    }
+}
+
+impl std::error::Error for FailedFiles {}
+
+/// Failure to add a file, along with where it's referenced from
+struct BuildError {
+    dependency: Dependency,
+    error: anyhow::Error,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.dependency.path.display(), self.error)?;
+        if let Some((file, attribute)) = &self.dependency.referrer {
+            write!(f, "\n  referenced by `{}` in {}", attribute, file.display())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Add every file reachable from a root file
+///
+/// HTML and Markdown files are followed through their relative
+/// paths, while anything else is copied as is. Each file is only
+/// visited once across the site, so reference cycles are fine.
+///
+async fn crawl(site: Arc<Site>, root_file_path: PathBuf) {
+    let mut queue: VecDeque<Dependency> = VecDeque::new();
+    queue.push_back(Dependency {
+        path: root_file_path,
+        referrer: None,
+    });
+
+    while let Some(dependency) = queue.pop_front() {
+        if site.is_stopped() {
+            return;
+        }
+        site.referenced
+            .lock()
+            .unwrap()
+            .insert(dependency.path.clone());
+
+        match add_dependency(&site, &dependency.path).await {
+            Ok(dependencies) => queue.extend(dependencies),
+            Err(error) => site
+                .errors
+                .lock()
+                .unwrap()
+                .push(BuildError { dependency, error }),
+        }
+    }
+}
+
+/// Add a file unless it's already visited, returning its dependencies
+///
+/// Skips building files which haven't changed since their last
+/// build, as long as their outputs are still there and none of
+/// their embeds depend on anything else.
+///
+async fn add_dependency(site: &Site, path: &Path) -> anyhow::Result<Vec<Dependency>> {
+    // Refuse paths escaping the root directory before reading them
+    let relative_path = site.output.relative_path(path)?;
+    let canonical_path = canonicalize(path).await.map_err(|err| match err.kind() {
+        io::ErrorKind::NotFound => anyhow!("Missing file"),
+        _ => anyhow!(err),
+    })?;
+    let is_new = site.visited.lock().unwrap().insert(canonical_path);
+    if !is_new {
+        return Ok(Vec::new());
+    }
+
+    let contents = read_file(path).await?;
+    let contents_digest = Digest::of(&contents);
+    let previous = site
+        .manifest
+        .lock()
+        .unwrap()
+        .files
+        .get(&relative_path)
+        .cloned();
+    let previous_embeds = match previous {
+        Some(record) if record.digest == contents_digest && !record.is_volatile => {
+            if let Some(dependencies) = reuse_record(site, path, &record).await? {
+                return Ok(dependencies);
+            }
+            record.embeds
+        }
+        Some(record) => record.embeds,
+        None => Default::default(),
+    };
+
+    let mut embeds = EmbedOutputs::new(previous_embeds);
+    let result = match FileKind::of(path) {
+        FileKind::HTMLPage => {
+            add_html_file(&site.output, path, &contents, site.minify, &mut embeds).await
+        }
+        FileKind::MarkdownPage => {
+            add_markdown_file(&site.output, path, &contents, site.minify, &mut embeds).await
+        }
+        FileKind::Asset => add_file(&site.output, path, &contents)
+            .await
+            .map(|()| Vec::new()),
+    };
+    // Even a failed build depends on its embed inputs, so fixing
+    // them rebuilds it
+    site.record_embed_inputs(path, embeds.take_input_paths());
+    let dependencies = result?;
+
+    let output_dir = site.output.output_dir();
+    let record = FileRecord {
+        digest: contents_digest,
+        outputs: site
+            .output
+            .outputs_of(path)?
+            .iter()
+            .filter_map(|output| output.strip_prefix(output_dir).ok())
+            .map(Path::to_path_buf)
+            .collect(),
+        dependencies: dependencies
+            .iter()
+            .filter_map(|dependency| {
+                let (_, attribute) = dependency.referrer.as_ref()?;
+                let path = dependency.path.strip_prefix(site.output.root_dir()).ok()?;
+                Some((attribute.clone(), path.to_path_buf()))
+            })
+            .collect(),
+        is_volatile: embeds.is_volatile(),
+        embeds: embeds.into_current(),
+    };
+    site.manifest
+        .lock()
+        .unwrap()
+        .files
+        .insert(relative_path, record);
+
+    Ok(dependencies)
+}
+
+/// Keep the outputs of an unchanged file, returning its dependencies
+///
+/// `None` if any of the outputs are missing, so it needs building.
+///
+async fn reuse_record(
+    site: &Site,
+    path: &Path,
+    record: &FileRecord,
+) -> anyhow::Result<Option<Vec<Dependency>>> {
+    let output_dir = site.output.output_dir();
+    for output in &record.outputs {
+        if !output_dir.join(output).is_file().await {
+            return Ok(None);
+        }
+    }
+    for output in &record.outputs {
+        site.output.claim(path, &output_dir.join(output))?;
+    }
+
+    let dependencies = record
+        .dependencies
+        .iter()
+        .map(|(attribute, dependency_path)| Dependency {
+            path: site.output.root_dir().join(dependency_path),
+            referrer: Some((path.to_path_buf(), attribute.clone())),
+        })
+        .collect();
+
+    Ok(Some(dependencies))
+}
+
+/// HTML and Markdown files directly within a directory
+async fn find_pages(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut pages = Vec::new();
+    let mut entries = read_dir(dir).await?;
+    while let Some(entry) = entries.next().await {
+        let path = entry?.path();
+        if FileKind::of(&path) != FileKind::Asset && path.is_file().await {
+            pages.push(path);
+        }
+    }
+    pages.sort();
+
+    Ok(pages)
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum FileKind {
+    HTMLPage,
+    MarkdownPage,
+    Asset,
+}
+
+impl FileKind {
+    pub fn of(path: &Path) -> Self {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("html") | Some("htm") => FileKind::HTMLPage,
+            Some("md") | Some("markdown") => FileKind::MarkdownPage,
+            _ => FileKind::Asset,
+        }
+    }
+}
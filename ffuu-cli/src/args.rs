@@ -1,4 +1,4 @@
-use clap::{App, Arg, ArgMatches};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 
 pub struct Arguments<'a> {
     matches: ArgMatches<'a>,
@@ -10,28 +10,116 @@ impl<'a> Arguments<'a> {
             .version("0.1")
             .author("Kevin Sullivan <kevin@sull.vn>")
             .about("Static site generator for people who hate static site generators")
+            .setting(AppSettings::SubcommandsNegateReqs)
+            .args(&build_args())
             .arg(
-                Arg::with_name("INPUT ROOT FILE")
-                    .help("Input root HTML file to process, recursively following relative URIs")
-                    .required(true)
-                    .index(1),
+                Arg::with_name("watch")
+                    .short("w")
+                    .long("watch")
+                    .help("Rebuild files as they change, until interrupted"),
             )
-            .arg(
-                Arg::with_name("OUTPUT DIR")
-                    .help("Output directory for processed files")
-                    .required(true)
-                    .index(2),
+            .subcommand(
+                SubCommand::with_name("serve")
+                    .about("Build and watch, serving the output directory with live reload")
+                    .args(&build_args())
+                    .arg(
+                        Arg::with_name("port")
+                            .short("p")
+                            .long("port")
+                            .takes_value(true)
+                            .default_value("8000")
+                            .help("Port to serve on, on localhost"),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("cache")
+                    .about("Manage the cache of embed outputs shared by every build")
+                    .setting(AppSettings::SubcommandRequiredElseHelp)
+                    .subcommand(SubCommand::with_name("clean").about("Remove every cached output")),
             )
             .get_matches();
 
         Arguments { matches }
     }
 
-    pub fn input_file_path(self: &Self) -> &str {
-        self.matches.value_of("INPUT ROOT FILE").unwrap()
+    pub fn input_path(&self) -> &str {
+        self.build_matches().value_of("INPUT").unwrap()
+    }
+
+    pub fn output_dir_path(&self) -> &str {
+        self.build_matches().value_of("OUTPUT DIR").unwrap()
+    }
+
+    pub fn minify(&self) -> bool {
+        self.build_matches().is_present("minify")
+    }
+
+    pub fn keep_going(&self) -> bool {
+        self.build_matches().is_present("keep-going")
+    }
+
+    pub fn clean(&self) -> bool {
+        self.build_matches().is_present("clean")
+    }
+
+    pub fn force(&self) -> bool {
+        self.build_matches().is_present("force")
     }
 
-    pub fn output_dir_path(self: &Self) -> &str {
-        self.matches.value_of("OUTPUT DIR").unwrap()
+    /// Serving watches too
+    pub fn watch(&self) -> bool {
+        self.matches.is_present("watch") || self.serve()
     }
+
+    pub fn serve(&self) -> bool {
+        self.matches.subcommand_matches("serve").is_some()
+    }
+
+    pub fn cache_clean(&self) -> bool {
+        self.matches
+            .subcommand_matches("cache")
+            .is_some_and(|matches| matches.subcommand_matches("clean").is_some())
+    }
+
+    pub fn port(&self) -> anyhow::Result<u16> {
+        let port = self.build_matches().value_of("port").unwrap_or("8000");
+        Ok(port.parse()?)
+    }
+
+    /// Matches for building, whether serving or not
+    fn build_matches(&self) -> &ArgMatches<'a> {
+        self.matches
+            .subcommand_matches("serve")
+            .unwrap_or(&self.matches)
+    }
+}
+
+/// Arguments for building, whether serving or not
+fn build_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("INPUT")
+            .help(
+                "Input root HTML or Markdown file, or directory of them, to process, \
+                 recursively following relative URIs",
+            )
+            .required(true)
+            .index(1),
+        Arg::with_name("OUTPUT DIR")
+            .help("Output directory for processed files")
+            .required(true)
+            .index(2),
+        Arg::with_name("minify")
+            .long("minify")
+            .help("Minify HTML output for production"),
+        Arg::with_name("keep-going")
+            .short("k")
+            .long("keep-going")
+            .help("Build as much as possible, instead of stopping at the first failure"),
+        Arg::with_name("clean")
+            .long("clean")
+            .help("Build everything from scratch, instead of only what changed"),
+        Arg::with_name("force")
+            .long("force")
+            .help("Take over the output directory, even if it doesn't look like ffuu built it"),
+    ]
 }
@@ -0,0 +1,278 @@
+use crate::digest::{Digest, Hasher};
+use anyhow::anyhow;
+use html_parse::HTMLEmbed;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// How long an embed's output may be reused, from its `cache`
+/// attribute
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CachePolicy {
+    /// `cache="false"`, executing on every build
+    Disabled,
+    /// Until the command, input, declared input files or environment
+    /// change, which is the default
+    Forever,
+    /// `cache="1h"` and the like, for commands with outside inputs
+    For(Duration),
+}
+
+impl CachePolicy {
+    pub fn of(embed: &HTMLEmbed) -> anyhow::Result<Self> {
+        let value = match embed.attribute_value("cache") {
+            Some(value) => value,
+            None => return Ok(CachePolicy::Forever),
+        };
+        match value.trim() {
+            "false" => Ok(CachePolicy::Disabled),
+            "true" => Ok(CachePolicy::Forever),
+            duration => parse_duration(duration)
+                .map(CachePolicy::For)
+                .ok_or_else(|| anyhow!("Invalid cache duration `{}`", duration)),
+        }
+    }
+}
+
+/// Seconds, or a number with an `s`, `m`, `h` or `d` suffix
+fn parse_duration(text: &str) -> Option<Duration> {
+    let (number, unit_seconds) = match text.char_indices().last()? {
+        (i, 's') => (&text[..i], 1),
+        (i, 'm') => (&text[..i], 60),
+        (i, 'h') => (&text[..i], 60 * 60),
+        (i, 'd') => (&text[..i], 24 * 60 * 60),
+        _ => (text, 1),
+    };
+    let seconds: u64 = number.trim().parse().ok()?;
+
+    Some(Duration::from_secs(seconds.checked_mul(unit_seconds)?))
+}
+
+/// Version of the cache key's fields, for changing them later
+const CACHE_KEY_VERSION: &[u8] = b"ffuu embed 1";
+
+/// Key of everything an embed's output depends on
+///
+/// Digest of the command, its input, the directory it runs in, the
+/// contents of the files listed in its `inputs` attribute, relative
+/// to that directory, and the environment variables it references
+/// along with `PATH`.
+///
+pub fn cache_key(embed: &HTMLEmbed, input_dir: &Path) -> anyhow::Result<Digest> {
+    // The directory of a page given by a bare file name is empty
+    let working_dir = Path::new(".").join(input_dir).canonicalize()?;
+
+    let mut hasher = Hasher::new();
+    hasher.field(CACHE_KEY_VERSION);
+    hasher.field(embed.command.as_bytes());
+    optional_field(&mut hasher, embed.input.as_deref());
+    hasher.field(working_dir.to_string_lossy().as_bytes());
+    let inputs = embed.attribute_value("inputs").unwrap_or_default();
+    let inputs: Vec<&str> = inputs.split_whitespace().collect();
+    hasher.field(&(inputs.len() as u64).to_be_bytes());
+    for input in inputs {
+        let contents = fs::read(input_dir.join(input))
+            .map_err(|err| anyhow!("Can't read embed input {}: {}", input, err))?;
+        hasher.field(input.as_bytes());
+        hasher.field(&contents);
+    }
+    let mut names = referenced_variables(&embed.command);
+    names.push("PATH".to_string());
+    hasher.field(&(names.len() as u64).to_be_bytes());
+    for name in names {
+        hasher.field(name.as_bytes());
+        optional_field(&mut hasher, env::var(&name).ok().as_deref());
+    }
+
+    Ok(hasher.finish())
+}
+
+/// Field which may be missing, kept apart from an empty one
+fn optional_field(hasher: &mut Hasher, value: Option<&str>) {
+    match value {
+        Some(value) => {
+            hasher.field(b"1");
+            hasher.field(value.as_bytes());
+        }
+        None => hasher.field(b"0"),
+    }
+}
+
+/// Names of `$NAME` and `${NAME}` variables in a shell command
+fn referenced_variables(command: &str) -> Vec<String> {
+    let mut names: Vec<String> = command
+        .split('$')
+        .skip(1)
+        .filter_map(|rest| {
+            let rest = rest.strip_prefix('{').unwrap_or(rest);
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            let name = &rest[..len];
+            let is_name = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_');
+            if is_name {
+                Some(name.to_string())
+            } else {
+                None
+            }
+        })
+        .collect();
+    names.sort();
+    names.dedup();
+
+    names
+}
+
+/// Cache of embed outputs shared by every build
+///
+/// `FFUU_CACHE_DIR`, or else `ffuu` in the user's cache directory.
+/// `None` without a home directory to put it in.
+///
+pub fn cache_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("FFUU_CACHE_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+    let cache_home = match env::var_os("XDG_CACHE_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".cache"),
+    };
+
+    Some(cache_home.join("ffuu"))
+}
+
+fn entry_path(key: Digest) -> Option<PathBuf> {
+    Some(cache_dir()?.join(key.to_string()))
+}
+
+/// Cached output, if there is one younger than the maximum age
+pub fn load(key: Digest, max_age: Option<Duration>) -> Option<String> {
+    let path = entry_path(key)?;
+    if let Some(max_age) = max_age {
+        let modified = fs::metadata(&path).and_then(|meta| meta.modified()).ok()?;
+        let age = SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default();
+        if age > max_age {
+            return None;
+        }
+    }
+
+    fs::read_to_string(path).ok()
+}
+
+/// Cache output for later builds
+///
+/// Written to a temporary file first, so concurrent builds never
+/// read half an entry. Failing to cache only costs a later build
+/// time, so it's just a warning.
+///
+pub fn store(key: Digest, output: &str) {
+    let path = match entry_path(key) {
+        Some(path) => path,
+        None => return,
+    };
+    let temporary_path = path.with_extension(format!("{}.tmp", std::process::id()));
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&temporary_path, output))
+        .and_then(|_| fs::rename(&temporary_path, &path));
+    if let Err(err) = result {
+        eprintln!("Warning: Can't cache embed output: {}", err);
+    }
+}
+
+/// Remove the cache, returning its directory if there was one
+pub fn clean() -> io::Result<Option<PathBuf>> {
+    let dir = match cache_dir() {
+        Some(dir) => dir,
+        None => return Ok(None),
+    };
+    match fs::remove_dir_all(&dir) {
+        Ok(()) => Ok(Some(dir)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use html_parse::{HTMLTag, HTMLTagKind, Span};
+
+    #[test]
+    fn parse_duration_units() {
+        assert_eq!(parse_duration("1h"), Some(Duration::from_secs(60 * 60)));
+        assert_eq!(
+            parse_duration("2d"),
+            Some(Duration::from_secs(2 * 24 * 60 * 60))
+        );
+        assert_eq!(parse_duration("5m"), Some(Duration::from_secs(5 * 60)));
+        assert_eq!(parse_duration("10s"), Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn parse_duration_seconds() {
+        assert_eq!(parse_duration("30"), Some(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn parse_duration_zero() {
+        assert_eq!(parse_duration("0m"), Some(Duration::from_secs(0)));
+    }
+
+    #[test]
+    fn parse_duration_overflow() {
+        assert_eq!(parse_duration(&format!("{}d", u64::MAX)), None);
+        assert_eq!(parse_duration(&format!("{}0", u64::MAX)), None);
+        assert_eq!(
+            parse_duration(&format!("{}", u64::MAX)),
+            Some(Duration::from_secs(u64::MAX))
+        );
+    }
+
+    #[test]
+    fn parse_duration_garbage() {
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("h"), None);
+        assert_eq!(parse_duration("1w"), None);
+        assert_eq!(parse_duration("-1h"), None);
+        assert_eq!(parse_duration("1.5h"), None);
+        assert_eq!(parse_duration("one hour"), None);
+    }
+
+    fn embed(command: &str, input: Option<&str>) -> HTMLEmbed<'static> {
+        HTMLEmbed {
+            command: command.to_owned().into(),
+            input: input.map(|input| input.to_owned().into()),
+            tag: HTMLTag {
+                kind: HTMLTagKind::Void,
+                name: "run".into(),
+                attributes: Vec::new(),
+                span: Span::default(),
+            },
+            span: Span::default(),
+        }
+    }
+
+    #[test]
+    fn cache_key_of_embed() {
+        let dir = env::temp_dir();
+        let key = |embed: HTMLEmbed| cache_key(&embed, &dir).unwrap();
+
+        assert_eq!(key(embed("date", None)), key(embed("date", None)));
+        assert_ne!(key(embed("date", None)), key(embed("date -u", None)));
+        assert_ne!(key(embed("cat", None)), key(embed("cat", Some(""))));
+        assert_ne!(key(embed("cat", Some("a"))), key(embed("cat", Some("b"))));
+    }
+
+    #[test]
+    fn referenced_variables_of_command() {
+        assert_eq!(
+            referenced_variables("echo $HOME ${USER}-$HOME $1 $$"),
+            vec!["HOME".to_string(), "USER".to_string()]
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use crate::digest::Digest;
+use crate::embed_cache::{self, cache_key, CachePolicy};
+use async_std::path::PathBuf;
+use exec_embed::exec_embed;
+use html_parse::HTMLEmbed;
+use std::collections::HashMap;
+use std::mem;
+use std::path::Path;
+
+/// Embed outputs of a file, reusing those from its last build
+///
+/// Embeds whose command, input, declared input files and environment
+/// are unchanged aren't executed again, whether they ran in the
+/// file's last build or in any build sharing the embed cache.
+///
+#[derive(Default)]
+pub struct EmbedOutputs {
+    previous: HashMap<Digest, String>,
+    current: HashMap<Digest, String>,
+    is_volatile: bool,
+    input_paths: Vec<PathBuf>,
+}
+
+impl EmbedOutputs {
+    pub fn new(previous: HashMap<Digest, String>) -> Self {
+        EmbedOutputs {
+            previous,
+            current: HashMap::new(),
+            is_volatile: false,
+            input_paths: Vec::new(),
+        }
+    }
+
+    /// Output of an embed in a file within the input directory,
+    /// which the embed runs in
+    ///
+    /// Failed embeds aren't cached, so they run again next time.
+    ///
+    pub fn get_or_exec(&mut self, embed: &HTMLEmbed, input_dir: &Path) -> anyhow::Result<String> {
+        if let Some(inputs) = embed.attribute_value("inputs") {
+            let input_paths = inputs
+                .split_whitespace()
+                .map(|input| input_dir.join(input).into());
+            self.input_paths.extend(input_paths);
+            self.is_volatile = true;
+        }
+        let policy = CachePolicy::of(embed)?;
+        if policy != CachePolicy::Forever {
+            self.is_volatile = true;
+        }
+        let key = cache_key(embed, input_dir)?;
+
+        let cached = match policy {
+            CachePolicy::Disabled => None,
+            CachePolicy::Forever => self
+                .previous
+                .get(&key)
+                .cloned()
+                .or_else(|| embed_cache::load(key, None)),
+            CachePolicy::For(max_age) => embed_cache::load(key, Some(max_age)),
+        };
+        let output = match cached {
+            Some(output) => output,
+            None => {
+                let output = exec_embed(embed, Some(input_dir))?;
+                if policy != CachePolicy::Disabled {
+                    embed_cache::store(key, &output);
+                }
+                output
+            }
+        };
+        self.current.insert(key, output.clone());
+
+        Ok(output)
+    }
+
+    /// Whether any output depends on more than the file itself, so
+    /// the file needs building even when it's unchanged
+    pub fn is_volatile(&self) -> bool {
+        self.is_volatile
+    }
+
+    /// Take the files declared as inputs by the embeds so far
+    pub fn take_input_paths(&mut self) -> Vec<PathBuf> {
+        mem::take(&mut self.input_paths)
+    }
+
+    /// Outputs of the embeds in this build
+    pub fn into_current(self) -> HashMap<Digest, String> {
+        self.current
+    }
+}
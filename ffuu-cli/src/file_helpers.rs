@@ -1,6 +1,10 @@
 use anyhow::anyhow;
-use async_std::fs::{read, write};
-use async_std::path::Path;
+use async_std::fs::{create_dir_all, read, write};
+use async_std::path::{Component, Path, PathBuf};
+use percent_encoding::percent_decode_str;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use url::Url;
 
 /// Add file to output directory
 pub async fn read_file<P: AsRef<Path>>(input_file_path: P) -> anyhow::Result<Vec<u8>> {
@@ -8,19 +12,238 @@ pub async fn read_file<P: AsRef<Path>>(input_file_path: P) -> anyhow::Result<Vec
     Ok(contents)
 }
 
-/// Add file to output directory
-pub async fn write_file<P: AsRef<Path>, Q: AsRef<Path>, C: AsRef<[u8]>>(
-    output_dir: P,
-    input_file_path: Q,
-    contents: C,
-) -> anyhow::Result<()> {
-    let input_file_name = input_file_path
-        .as_ref()
-        .file_name()
-        .ok_or(anyhow!("No filename: {:?}", input_file_path.as_ref()))?;
-    let output_path = output_dir.as_ref().join(input_file_name);
-
-    write(output_path, contents).await?;
-
-    Ok(())
+/// Output directory mirroring the input tree
+///
+/// Input files are written relative to the root directory,
+/// keeping their directory structure. Paths escaping the root
+/// directory, and input files landing on the same output path, are
+/// errors.
+///
+pub struct OutputDir {
+    output_dir: PathBuf,
+    root_dir: PathBuf,
+    /// Input file each output path is written from, relative to the
+    /// root directory
+    written: Mutex<HashMap<PathBuf, PathBuf>>,
+}
+
+impl OutputDir {
+    pub fn new<P: AsRef<Path>, Q: AsRef<Path>>(output_dir: P, root_dir: Q) -> Self {
+        OutputDir {
+            output_dir: output_dir.as_ref().to_path_buf(),
+            root_dir: root_dir.as_ref().to_path_buf(),
+            written: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn output_dir(&self) -> &Path {
+        &self.output_dir
+    }
+
+    pub fn root_dir(&self) -> &Path {
+        &self.root_dir
+    }
+
+    /// Path of an input file relative to the root directory
+    ///
+    /// Without any `.` or `..`, so each file has only the one.
+    ///
+    pub fn relative_path<P: AsRef<Path>>(&self, input_file_path: P) -> anyhow::Result<PathBuf> {
+        let input_file_path = input_file_path.as_ref();
+        let escapes_root = || anyhow!("Outside of root directory: {}", input_file_path.display());
+        let relative_path = input_file_path
+            .strip_prefix(&self.root_dir)
+            .map_err(|_| escapes_root())?;
+
+        let mut normalized_path = PathBuf::new();
+        let mut depth = 0;
+        for component in relative_path.components() {
+            match component {
+                Component::Normal(name) => {
+                    normalized_path.push(name);
+                    depth += 1;
+                }
+                Component::CurDir => {}
+                Component::ParentDir if depth > 0 => {
+                    normalized_path.pop();
+                    depth -= 1;
+                }
+                Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                    return Err(escapes_root())
+                }
+            }
+        }
+        if depth == 0 {
+            return Err(anyhow!("No filename: {}", input_file_path.display()));
+        }
+
+        Ok(normalized_path)
+    }
+
+    /// Output path of an input file
+    pub fn output_path<P: AsRef<Path>>(&self, input_file_path: P) -> anyhow::Result<PathBuf> {
+        Ok(self.output_dir.join(self.relative_path(input_file_path)?))
+    }
+
+    /// Write an input file's contents to its output path
+    pub async fn write_file<P: AsRef<Path>, C: AsRef<[u8]>>(
+        &self,
+        input_file_path: P,
+        contents: C,
+    ) -> anyhow::Result<()> {
+        let output_path = self.output_path(&input_file_path)?;
+        self.write(input_file_path.as_ref(), output_path, contents)
+            .await
+    }
+
+    /// Write an input file's contents to its output path, with
+    /// another extension
+    pub async fn write_file_with_extension<P: AsRef<Path>, C: AsRef<[u8]>>(
+        &self,
+        input_file_path: P,
+        extension: &str,
+        contents: C,
+    ) -> anyhow::Result<()> {
+        let output_path = self
+            .output_path(&input_file_path)?
+            .with_extension(extension);
+        self.write(input_file_path.as_ref(), output_path, contents)
+            .await
+    }
+
+    async fn write<C: AsRef<[u8]>>(
+        &self,
+        input_file_path: &Path,
+        output_path: PathBuf,
+        contents: C,
+    ) -> anyhow::Result<()> {
+        self.claim(input_file_path, &output_path)?;
+        if let Some(parent) = output_path.parent() {
+            create_dir_all(parent).await?;
+        }
+        write(output_path, contents).await?;
+
+        Ok(())
+    }
+
+    /// Claim an output path for an input file, unless another input
+    /// file already has
+    ///
+    /// The same input file may claim it again, as when rebuilding.
+    ///
+    pub fn claim(&self, input_file_path: &Path, output_path: &Path) -> anyhow::Result<()> {
+        let relative_path = self.relative_path(input_file_path)?;
+        let previous_input = self
+            .written
+            .lock()
+            .unwrap()
+            .entry(output_path.to_path_buf())
+            .or_insert_with(|| relative_path.clone())
+            .clone();
+        if previous_input != relative_path {
+            return Err(anyhow!(
+                "Output path {} written from both {} and {}",
+                output_path.display(),
+                previous_input.display(),
+                relative_path.display()
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Give up the output paths claimed for an input file, by its
+    /// root relative path, once it's no longer part of the site
+    pub fn release(&self, relative_path: &Path) {
+        self.written
+            .lock()
+            .unwrap()
+            .retain(|_, input| input != relative_path);
+    }
+
+    /// Whether any input file claimed an output path
+    pub fn is_claimed(&self, output_path: &Path) -> bool {
+        self.written.lock().unwrap().contains_key(output_path)
+    }
+
+    /// Output paths claimed for an input file
+    pub fn outputs_of(&self, input_file_path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+        let relative_path = self.relative_path(input_file_path)?;
+        let mut outputs: Vec<PathBuf> = self
+            .written
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, input)| **input == relative_path)
+            .map(|(output, _)| output.clone())
+            .collect();
+        outputs.sort();
+
+        Ok(outputs)
+    }
+}
+
+/// File path referenced by a URI, if it's relative
+///
+/// Drops any query or fragment. `None` for absolute URLs and paths,
+/// and for references within the same document.
+///
+pub fn relative_path(uri: &str) -> Option<&str> {
+    if Url::parse(uri).is_ok() {
+        return None;
+    }
+
+    let end = uri.find(['?', '#']).unwrap_or(uri.len());
+    let path = &uri[..end];
+    if path.is_empty() || !Path::new(path).is_relative() {
+        return None;
+    }
+
+    Some(path)
+}
+
+/// Relative path of the file a URI references, percent-decoded
+///
+/// `None` where `relative_path` is, and for paths with an encoded
+/// `/` or `\`, which can't name a file any server would serve.
+///
+pub fn relative_file_path(uri: &str) -> Option<String> {
+    let encoded_path = relative_path(uri)?;
+    let path = percent_decode_str(encoded_path).decode_utf8_lossy();
+    let separator_count = |path: &str| path.matches(['/', '\\']).count();
+    if separator_count(&path) != separator_count(encoded_path) {
+        return None;
+    }
+
+    Some(path.into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_file_path_decoded() {
+        assert_eq!(
+            relative_file_path("./my%20post.md?a=%20#b"),
+            Some("./my post.md".to_string())
+        );
+        assert_eq!(
+            relative_file_path("caf%C3%A9/index.html"),
+            Some("café/index.html".to_string())
+        );
+    }
+
+    #[test]
+    fn relative_file_path_encoded_separator() {
+        assert_eq!(relative_file_path("a%2Fb.html"), None);
+        assert_eq!(relative_file_path("a%5Cb.html"), None);
+    }
+
+    #[test]
+    fn relative_file_path_not_relative() {
+        assert_eq!(relative_file_path("https://example.com/a.html"), None);
+        assert_eq!(relative_file_path("/a.html"), None);
+        assert_eq!(relative_file_path("#top"), None);
+    }
 }
@@ -1,31 +1,77 @@
-use async_std::fs::{create_dir_all, remove_dir_all};
-use async_std::io;
-
 mod add_file;
 mod add_html_file;
+mod add_markdown_file;
+mod add_site;
 mod args;
+mod digest;
+mod embed_cache;
+mod embed_outputs;
 mod file_helpers;
+mod manifest;
+mod output_dir;
+mod serve;
+mod watch;
 
-use add_html_file::add_html_file;
+use add_site::{FailedFiles, Site};
 use args::Arguments;
+use async_std::path::Path;
+use async_std::task::spawn;
+use manifest::Manifest;
+use output_dir::{check_output_dir, discard, prepare_output_dir, prepare_staging_dir, swap_in};
+use serve::{serve, LiveReload};
+use std::sync::Arc;
+use watch::watch;
 
 #[async_std::main]
 async fn main() -> anyhow::Result<()> {
     let args = Arguments::parse();
-    prepare_output_dir(args.output_dir_path()).await?;
-    add_html_file(args.output_dir_path(), args.input_file_path()).await?;
+    if args.cache_clean() {
+        match embed_cache::clean()? {
+            Some(dir) => println!("Removed {}", dir.display()),
+            None => println!("Nothing cached"),
+        }
+        return Ok(());
+    }
 
-    Ok(())
-}
+    let output_dir = Path::new(args.output_dir_path());
+    check_output_dir(output_dir, Path::new(args.input_path()), args.force()).await?;
+    let manifest = match Manifest::load(output_dir).await {
+        Some(manifest) if !args.clean() && manifest.minify == args.minify() => Some(manifest),
+        _ => None,
+    };
+
+    // Watching builds in place, so rebuilds show up right away
+    if args.watch() {
+        let manifest = prepare_output_dir(output_dir, manifest, args.minify()).await?;
+        // Watching keeps going, so later rebuilds have everything to go on
+        let site = Arc::new(Site::new(output_dir, args.input_path(), manifest, true).await?);
+
+        return if args.serve() {
+            let live_reload = Arc::new(LiveReload::default());
+            let server = spawn(serve(
+                output_dir.to_path_buf(),
+                args.port()?,
+                live_reload.clone(),
+            ));
+            watch(site, || live_reload.reload()).await?;
+            server.await
+        } else {
+            watch(site, || {}).await
+        };
+    }
 
-/// Prepare output directory
-///
-/// Clear and create it.
-///
-async fn prepare_output_dir(path: &str) -> io::Result<()> {
-    match remove_dir_all(path).await {
-        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
-        r => r,
-    }?;
-    create_dir_all(path).await
+    let (staging_dir, manifest) = prepare_staging_dir(output_dir, manifest, args.minify()).await?;
+    let site = Site::new(&staging_dir, args.input_path(), manifest, args.keep_going()).await?;
+    match Arc::new(site).build().await {
+        Ok(()) => swap_in(&staging_dir, output_dir).await,
+        // Keeping going is for getting everything else built, so keep it
+        Err(err) if args.keep_going() && err.is::<FailedFiles>() => {
+            swap_in(&staging_dir, output_dir).await?;
+            Err(err)
+        }
+        Err(err) => {
+            discard(&staging_dir).await?;
+            Err(err)
+        }
+    }
 }
@@ -0,0 +1,276 @@
+use async_std::fs::{read_to_string, write};
+use async_std::path::{Path, PathBuf};
+use std::collections::HashMap;
+
+use crate::digest::Digest;
+
+const MANIFEST_FILE_NAME: &str = ".ffuu-manifest";
+const MANIFEST_HEADER: &str = "ffuu-manifest 2";
+
+/// Record of the last build, kept in the output directory
+///
+/// Lets the next build skip files which haven't changed since, and
+/// delete outputs of files which are gone.
+///
+pub struct Manifest {
+    /// Whether output is minified, which every output depends on
+    pub minify: bool,
+    /// Records by input path, relative to the root directory
+    pub files: HashMap<PathBuf, FileRecord>,
+}
+
+/// What an input file was built into
+#[derive(Clone, Default)]
+pub struct FileRecord {
+    pub digest: Digest,
+    /// Output paths, relative to the output directory
+    pub outputs: Vec<PathBuf>,
+    /// Attribute and path, relative to the root directory, of each
+    /// referenced file
+    pub dependencies: Vec<(String, PathBuf)>,
+    /// Embed outputs by cache key
+    pub embeds: HashMap<Digest, String>,
+    /// Whether embed outputs depend on more than the file itself,
+    /// like declared input files or a cache duration
+    pub is_volatile: bool,
+}
+
+impl Manifest {
+    pub fn new(minify: bool) -> Self {
+        Manifest {
+            minify,
+            files: HashMap::new(),
+        }
+    }
+
+    /// Load the manifest from an output directory
+    ///
+    /// `None` if there isn't one, or it can't be read, in which case
+    /// everything needs building.
+    ///
+    pub async fn load<P: AsRef<Path>>(output_dir: P) -> Option<Self> {
+        let text = read_to_string(output_dir.as_ref().join(MANIFEST_FILE_NAME))
+            .await
+            .ok()?;
+        Manifest::parse(&text)
+    }
+
+    /// Save manifest text, from `to_text`, to an output directory
+    pub async fn save<P: AsRef<Path>>(output_dir: P, text: String) -> anyhow::Result<()> {
+        write(output_dir.as_ref().join(MANIFEST_FILE_NAME), text).await?;
+        Ok(())
+    }
+
+    /// One line per field, with tab separated values
+    pub fn to_text(&self) -> String {
+        let mut lines = vec![
+            MANIFEST_HEADER.to_string(),
+            format!("minify\t{}", self.minify),
+        ];
+
+        let mut input_paths: Vec<&PathBuf> = self.files.keys().collect();
+        input_paths.sort();
+        for input_path in input_paths {
+            let record = &self.files[input_path];
+            lines.push(format!(
+                "file\t{}\t{}",
+                record.digest,
+                escape_path(input_path)
+            ));
+            if record.is_volatile {
+                lines.push("volatile".to_string());
+            }
+            for output in &record.outputs {
+                lines.push(format!("output\t{}", escape_path(output)));
+            }
+            for (attribute, path) in &record.dependencies {
+                lines.push(format!(
+                    "dependency\t{}\t{}",
+                    escape(attribute),
+                    escape_path(path)
+                ));
+            }
+            for (key, output) in &record.embeds {
+                lines.push(format!("embed\t{}\t{}", key, escape(output)));
+            }
+        }
+
+        lines.join("\n") + "\n"
+    }
+
+    fn parse(text: &str) -> Option<Self> {
+        let mut lines = text.lines();
+        if lines.next()? != MANIFEST_HEADER {
+            return None;
+        }
+
+        let mut minify = false;
+        let mut files: Vec<(PathBuf, FileRecord)> = Vec::new();
+        for line in lines {
+            let fields: Vec<&str> = line.split('\t').collect();
+            match fields.as_slice() {
+                ["minify", value] => minify = value.parse().ok()?,
+                ["file", digest, path] => {
+                    let record = FileRecord {
+                        digest: digest.parse().ok()?,
+                        ..FileRecord::default()
+                    };
+                    files.push((PathBuf::from(unescape(path)?), record));
+                }
+                ["volatile"] => {
+                    let (_, record) = files.last_mut()?;
+                    record.is_volatile = true;
+                }
+                ["output", path] => {
+                    let (_, record) = files.last_mut()?;
+                    record.outputs.push(PathBuf::from(unescape(path)?));
+                }
+                ["dependency", attribute, path] => {
+                    let (_, record) = files.last_mut()?;
+                    let dependency = (unescape(attribute)?, PathBuf::from(unescape(path)?));
+                    record.dependencies.push(dependency);
+                }
+                ["embed", key, output] => {
+                    let (_, record) = files.last_mut()?;
+                    let key = key.parse().ok()?;
+                    record.embeds.insert(key, unescape(output)?);
+                }
+                _ => return None,
+            }
+        }
+
+        Some(Manifest {
+            minify,
+            files: files.into_iter().collect(),
+        })
+    }
+}
+
+fn escape_path(path: &Path) -> String {
+    escape(&path.to_string_lossy())
+}
+
+/// Escape backslashes, tabs and line breaks, keeping values on a line
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+fn unescape(value: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next()? {
+            '\\' => unescaped.push('\\'),
+            't' => unescaped.push('\t'),
+            'n' => unescaped.push('\n'),
+            'r' => unescaped.push('\r'),
+            _ => return None,
+        }
+    }
+
+    Some(unescaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest() -> Manifest {
+        let mut manifest = Manifest::new(true);
+        let record = FileRecord {
+            digest: Digest::of(b"contents"),
+            outputs: vec![PathBuf::from("odd\tname\n.html")],
+            dependencies: vec![
+                ("href".to_string(), PathBuf::from("a\\b.css")),
+                ("src".to_string(), PathBuf::from("line\r\nbreak.png")),
+            ],
+            embeds: vec![(Digest::of(b"embed"), "<p>\n\tHi\n</p>".to_string())]
+                .into_iter()
+                .collect(),
+            is_volatile: true,
+        };
+        manifest
+            .files
+            .insert(PathBuf::from("odd\tname\n.md"), record);
+        manifest
+            .files
+            .insert(PathBuf::from("index.html"), FileRecord::default());
+
+        manifest
+    }
+
+    #[test]
+    fn manifest_round_trip() {
+        let text = manifest().to_text();
+        let parsed = Manifest::parse(&text).unwrap();
+
+        assert_eq!(parsed.to_text(), text);
+        assert!(parsed.minify);
+        assert_eq!(parsed.files.len(), 2);
+        assert!(!parsed.files[&PathBuf::from("index.html")].is_volatile);
+    }
+
+    #[test]
+    fn manifest_round_trip_odd_paths() {
+        let text = manifest().to_text();
+        let parsed = Manifest::parse(&text).unwrap();
+        let record = &parsed.files[&PathBuf::from("odd\tname\n.md")];
+
+        assert_eq!(record.digest, Digest::of(b"contents"));
+        assert_eq!(record.outputs, vec![PathBuf::from("odd\tname\n.html")]);
+        assert_eq!(
+            record.dependencies,
+            vec![
+                ("href".to_string(), PathBuf::from("a\\b.css")),
+                ("src".to_string(), PathBuf::from("line\r\nbreak.png")),
+            ]
+        );
+        assert_eq!(record.embeds[&Digest::of(b"embed")], "<p>\n\tHi\n</p>");
+        assert!(record.is_volatile);
+    }
+
+    #[test]
+    fn manifest_one_line_per_field() {
+        let text = manifest().to_text();
+
+        assert_eq!(text.lines().count(), 9);
+        let file_line = format!("file\t{}\todd\\tname\\n.md\n", Digest::of(b"contents"));
+        assert!(text.contains(&file_line));
+    }
+
+    #[test]
+    fn manifest_parse_invalid() {
+        let digest = Digest::of(b"contents");
+        assert!(Manifest::parse("").is_none());
+        assert!(Manifest::parse("ffuu-manifest 1\n").is_none());
+        assert!(Manifest::parse("ffuu-manifest 2\noutput\ta.html\n").is_none());
+        assert!(Manifest::parse("ffuu-manifest 2\nfile\tbeef\ta.html\n").is_none());
+        assert!(
+            Manifest::parse(&format!("ffuu-manifest 2\nfile\t{}\ta\\x.html\n", digest)).is_none()
+        );
+        assert!(Manifest::parse(&format!("ffuu-manifest 2\nfile\t{}\ta\\\n", digest)).is_none());
+    }
+
+    #[test]
+    fn escape_round_trip() {
+        let value = "a\\tb\t\n\r\\";
+
+        assert_eq!(escape(value), "a\\\\tb\\t\\n\\r\\\\");
+        assert_eq!(unescape(&escape(value)).unwrap(), value);
+    }
+}
@@ -0,0 +1,162 @@
+use crate::manifest::Manifest;
+use anyhow::anyhow;
+use async_std::fs::{canonicalize, copy, create_dir_all, read_dir, remove_dir_all, rename, write};
+use async_std::io;
+use async_std::path::{Path, PathBuf};
+use async_std::prelude::StreamExt;
+use std::env;
+use std::future::Future;
+use std::pin::Pin;
+
+const MARKER_FILE_NAME: &str = ".ffuu-output";
+const MARKER_CONTENTS: &str = "Built by ffuu, which may clear this directory on every build\n";
+
+/// Refuse to take over directories which clearing would be a disaster
+/// for
+///
+/// The output directory has to be missing, empty or marked as built
+/// by ffuu. It also can't hold the input, or be a home directory or
+/// the file system root. Forcing skips these checks.
+///
+pub async fn check_output_dir(
+    output_dir: &Path,
+    input_path: &Path,
+    force: bool,
+) -> anyhow::Result<()> {
+    if force || !output_dir.exists().await {
+        return Ok(());
+    }
+
+    let output_dir_canonical = canonicalize(output_dir).await?;
+    let input_canonical = canonicalize(input_path).await?;
+    let is_home_dir = match env::var_os("HOME") {
+        Some(home_dir) => canonicalize(home_dir)
+            .await
+            .is_ok_and(|home_dir| home_dir == output_dir_canonical),
+        None => false,
+    };
+    if output_dir_canonical.parent().is_none()
+        || is_home_dir
+        || input_canonical.starts_with(&output_dir_canonical)
+    {
+        return Err(anyhow!(
+            "Refusing to clear {}, which holds the input, is a home directory or is the \
+             file system root. Use --force to clear it anyway",
+            output_dir.display()
+        ));
+    }
+
+    let is_marked = output_dir.join(MARKER_FILE_NAME).is_file().await;
+    let is_empty = read_dir(output_dir).await?.next().await.is_none();
+    if !is_marked && !is_empty {
+        return Err(anyhow!(
+            "Refusing to clear {}, which wasn't built by ffuu. Use --force to clear it anyway",
+            output_dir.display()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Prepare output directory to build in place
+///
+/// Without a manifest to build on, clear it so everything is built
+/// from scratch.
+///
+pub async fn prepare_output_dir(
+    output_dir: &Path,
+    manifest: Option<Manifest>,
+    minify: bool,
+) -> io::Result<Manifest> {
+    if manifest.is_none() {
+        remove_dir_if_exists(output_dir).await?;
+    }
+    create_dir_all(output_dir).await?;
+    write(output_dir.join(MARKER_FILE_NAME), MARKER_CONTENTS).await?;
+
+    Ok(manifest.unwrap_or_else(|| Manifest::new(minify)))
+}
+
+/// Prepare a staging directory to build in, instead of the output
+/// directory itself
+///
+/// Next to the output directory, so it can be swapped in once the
+/// build succeeds. Starts as a copy of the output directory when
+/// there's a manifest to build on, and empty otherwise.
+///
+pub async fn prepare_staging_dir(
+    output_dir: &Path,
+    manifest: Option<Manifest>,
+    minify: bool,
+) -> anyhow::Result<(PathBuf, Manifest)> {
+    let staging_dir = sibling_dir(output_dir, "staging")?;
+    remove_dir_if_exists(&staging_dir).await?;
+    if manifest.is_some() {
+        copy_dir(output_dir.to_path_buf(), staging_dir.clone()).await?;
+    }
+    let manifest = prepare_output_dir(&staging_dir, manifest, minify).await?;
+
+    Ok((staging_dir, manifest))
+}
+
+/// Replace output directory with a built staging directory
+///
+/// Either the build succeeded, or it kept going past failed files,
+/// which keep their previous outputs, if any.
+///
+/// The old output is moved aside before the staging directory takes
+/// its place, so the output directory is only ever missing for the
+/// moment between the two renames, and never half built.
+///
+pub async fn swap_in(staging_dir: &Path, output_dir: &Path) -> anyhow::Result<()> {
+    let old_dir = sibling_dir(output_dir, "old")?;
+    remove_dir_if_exists(&old_dir).await?;
+    if output_dir.exists().await {
+        rename(output_dir, &old_dir).await?;
+    }
+    rename(staging_dir, output_dir).await?;
+    remove_dir_if_exists(&old_dir).await?;
+
+    Ok(())
+}
+
+/// Throw away a staging directory after a failed build
+pub async fn discard(staging_dir: &Path) -> io::Result<()> {
+    remove_dir_if_exists(staging_dir).await
+}
+
+/// Hidden directory next to output directory, for a purpose
+fn sibling_dir(output_dir: &Path, purpose: &str) -> anyhow::Result<PathBuf> {
+    let name = output_dir
+        .file_name()
+        .ok_or(anyhow!("No directory name: {}", output_dir.display()))?;
+    let sibling_name = format!(".{}.ffuu-{}", name.to_string_lossy(), purpose);
+
+    Ok(output_dir.with_file_name(sibling_name))
+}
+
+async fn remove_dir_if_exists(path: &Path) -> io::Result<()> {
+    match remove_dir_all(path).await {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        r => r,
+    }
+}
+
+/// Copy a directory recursively
+fn copy_dir(from: PathBuf, to: PathBuf) -> Pin<Box<dyn Future<Output = io::Result<()>> + Send>> {
+    Box::pin(async move {
+        create_dir_all(&to).await?;
+        let mut entries = read_dir(&from).await?;
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            let to_path = to.join(entry.file_name());
+            if entry.file_type().await?.is_dir() {
+                copy_dir(entry.path(), to_path).await?;
+            } else {
+                copy(entry.path(), to_path).await?;
+            }
+        }
+
+        Ok(())
+    })
+}
@@ -0,0 +1,322 @@
+use anyhow::anyhow;
+use async_std::fs::read;
+use async_std::io::prelude::{BufReadExt, WriteExt};
+use async_std::io::BufReader;
+use async_std::net::{TcpListener, TcpStream};
+use async_std::path::{Path, PathBuf};
+use async_std::prelude::StreamExt;
+use async_std::task::{sleep, spawn};
+use percent_encoding::percent_decode_str;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+const LIVE_RELOAD_PATH: &str = "/_ffuu/live-reload";
+const LIVE_RELOAD_SCRIPT: &str = "<script>\
+    new EventSource(\"/_ffuu/live-reload\").onmessage = () => location.reload()\
+    </script>";
+const LIVE_RELOAD_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Tells pages to reload once a build finishes
+#[derive(Default)]
+pub struct LiveReload {
+    build_count: AtomicUsize,
+}
+
+impl LiveReload {
+    pub fn reload(&self) {
+        self.build_count.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn build_count(&self) -> usize {
+        self.build_count.load(Ordering::SeqCst)
+    }
+}
+
+/// Serve output directory over HTTP on localhost
+///
+/// HTML pages get a script which reloads them through server-sent
+/// events whenever live reload is triggered.
+///
+pub async fn serve(
+    output_dir: PathBuf,
+    port: u16,
+    live_reload: Arc<LiveReload>,
+) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+    println!(
+        "Serving {} at http://localhost:{}/",
+        output_dir.display(),
+        port
+    );
+
+    let output_dir = Arc::new(output_dir);
+    let mut incoming = listener.incoming();
+    while let Some(stream) = incoming.next().await {
+        let stream = stream?;
+        let output_dir = output_dir.clone();
+        let live_reload = live_reload.clone();
+        spawn(async move {
+            if let Err(err) = handle_request(stream, &output_dir, &live_reload).await {
+                eprintln!("Error: Can't serve request: {}", err);
+            }
+        });
+    }
+
+    Ok(())
+}
+
+async fn handle_request(
+    mut stream: TcpStream,
+    output_dir: &Path,
+    live_reload: &LiveReload,
+) -> anyhow::Result<()> {
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    // Headers don't matter
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+
+    let mut request = request_line.split_whitespace();
+    let (method, target) = match (request.next(), request.next()) {
+        (Some(method), Some(target)) => (method, target),
+        _ => return Err(anyhow!("Malformed request line: {:?}", request_line)),
+    };
+    if method != "GET" && method != "HEAD" {
+        return respond(&mut stream, "405 Method Not Allowed", "text/plain", b"").await;
+    }
+    let path = target.split(['?', '#']).next().unwrap_or("/");
+
+    if path == LIVE_RELOAD_PATH {
+        return stream_live_reload(&mut stream, live_reload).await;
+    }
+
+    let file_path = match resolve_path(output_dir, path) {
+        Some(file_path) => file_path,
+        None => return respond(&mut stream, "404 Not Found", "text/plain", b"Not found").await,
+    };
+    if file_path.is_dir().await {
+        if !path.ends_with('/') {
+            // So relative URIs in the index resolve within the directory
+            let location = format!("{}/", path);
+            return redirect(&mut stream, &location).await;
+        }
+        return respond_file(&mut stream, file_path.join("index.html"), method).await;
+    }
+
+    respond_file(&mut stream, file_path, method).await
+}
+
+/// File path within output directory for a request path
+///
+/// `None` for paths which could escape it.
+///
+fn resolve_path(output_dir: &Path, path: &str) -> Option<PathBuf> {
+    let mut file_path = output_dir.to_path_buf();
+    for segment in path.split('/') {
+        let segment = percent_decode_str(segment).decode_utf8().ok()?;
+        match segment.as_ref() {
+            "" | "." => {}
+            ".." => return None,
+            s if s.contains(['/', '\\']) => return None,
+            s => file_path.push(s),
+        }
+    }
+
+    Some(file_path)
+}
+
+async fn respond_file(
+    stream: &mut TcpStream,
+    file_path: PathBuf,
+    method: &str,
+) -> anyhow::Result<()> {
+    let contents = match read(&file_path).await {
+        Ok(contents) => contents,
+        Err(_) => return respond(stream, "404 Not Found", "text/plain", b"Not found").await,
+    };
+    let mime_type = mime_type(&file_path);
+    let body = if mime_type.starts_with("text/html") {
+        inject_live_reload(contents)
+    } else {
+        contents
+    };
+
+    if method == "HEAD" {
+        write_head(stream, "200 OK", mime_type, body.len()).await
+    } else {
+        respond(stream, "200 OK", mime_type, &body).await
+    }
+}
+
+/// Add the live reload script to an HTML page, before `</body>` if
+/// there is one
+fn inject_live_reload(html: Vec<u8>) -> Vec<u8> {
+    let body_end = html
+        .windows(7)
+        .rposition(|window| window.eq_ignore_ascii_case(b"</body>"))
+        .unwrap_or(html.len());
+
+    let mut injected = Vec::with_capacity(html.len() + LIVE_RELOAD_SCRIPT.len());
+    injected.extend_from_slice(&html[..body_end]);
+    injected.extend_from_slice(LIVE_RELOAD_SCRIPT.as_bytes());
+    injected.extend_from_slice(&html[body_end..]);
+
+    injected
+}
+
+/// Send an event whenever live reload is triggered, until the page
+/// goes away
+async fn stream_live_reload(
+    stream: &mut TcpStream,
+    live_reload: &LiveReload,
+) -> anyhow::Result<()> {
+    stream
+        .write_all(
+            b"HTTP/1.1 200 OK\r\n\
+              Content-Type: text/event-stream\r\n\
+              Cache-Control: no-cache\r\n\
+              Connection: keep-alive\r\n\r\n",
+        )
+        .await?;
+
+    let mut build_count = live_reload.build_count();
+    loop {
+        sleep(LIVE_RELOAD_POLL_INTERVAL).await;
+        if live_reload.build_count() != build_count {
+            build_count = live_reload.build_count();
+            stream.write_all(b"data: reload\n\n").await?;
+        }
+    }
+}
+
+async fn redirect(stream: &mut TcpStream, location: &str) -> anyhow::Result<()> {
+    let head = format!(
+        "HTTP/1.1 301 Moved Permanently\r\n\
+         Location: {}\r\n\
+         Content-Length: 0\r\n\
+         Connection: close\r\n\r\n",
+        location
+    );
+    stream.write_all(head.as_bytes()).await?;
+
+    Ok(())
+}
+
+async fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    mime_type: &str,
+    body: &[u8],
+) -> anyhow::Result<()> {
+    write_head(stream, status, mime_type, body.len()).await?;
+    stream.write_all(body).await?;
+
+    Ok(())
+}
+
+async fn write_head(
+    stream: &mut TcpStream,
+    status: &str,
+    mime_type: &str,
+    content_length: usize,
+) -> anyhow::Result<()> {
+    let head = format!(
+        "HTTP/1.1 {}\r\n\
+         Content-Type: {}\r\n\
+         Content-Length: {}\r\n\
+         Cache-Control: no-cache\r\n\
+         Connection: close\r\n\r\n",
+        status, mime_type, content_length
+    );
+    stream.write_all(head.as_bytes()).await?;
+
+    Ok(())
+}
+
+fn mime_type(file_path: &Path) -> &'static str {
+    let extension = file_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+
+    match extension.as_deref() {
+        Some("html") | Some("htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") | Some("mjs") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("txt") | Some("md") => "text/plain; charset=utf-8",
+        Some("xml") => "application/xml",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("avif") => "image/avif",
+        Some("ico") => "image/x-icon",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        Some("ttf") => "font/ttf",
+        Some("otf") => "font/otf",
+        Some("pdf") => "application/pdf",
+        Some("wasm") => "application/wasm",
+        Some("mp3") => "audio/mpeg",
+        Some("mp4") => "video/mp4",
+        Some("webm") => "video/webm",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(path: &str) -> Option<PathBuf> {
+        resolve_path(Path::new("/out"), path)
+    }
+
+    #[test]
+    fn resolve_plain_path() {
+        assert_eq!(resolve("/a/b.html"), Some(PathBuf::from("/out/a/b.html")));
+        assert_eq!(
+            resolve("/./a//b.html"),
+            Some(PathBuf::from("/out/a/b.html"))
+        );
+        assert_eq!(resolve("/"), Some(PathBuf::from("/out")));
+    }
+
+    #[test]
+    fn resolve_encoded_path() {
+        assert_eq!(resolve("/a%20b.html"), Some(PathBuf::from("/out/a b.html")));
+    }
+
+    #[test]
+    fn resolve_rejects_parent() {
+        assert_eq!(resolve("/../secret"), None);
+        assert_eq!(resolve("/a/../../secret"), None);
+    }
+
+    #[test]
+    fn resolve_rejects_encoded_parent() {
+        assert_eq!(resolve("/%2e%2e/secret"), None);
+        assert_eq!(resolve("/%2E%2E/secret"), None);
+        assert_eq!(resolve("/.%2e/secret"), None);
+    }
+
+    #[test]
+    fn resolve_rejects_encoded_separators() {
+        assert_eq!(resolve("/..%2Fsecret"), None);
+        assert_eq!(resolve("/a%2fb"), None);
+        assert_eq!(resolve("/..%5Csecret"), None);
+    }
+
+    #[test]
+    fn resolve_rejects_invalid_utf8() {
+        assert_eq!(resolve("/%ff"), None);
+    }
+}
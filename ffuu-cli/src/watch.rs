@@ -0,0 +1,94 @@
+use crate::add_site::Site;
+use async_std::fs::metadata;
+use async_std::path::{Path, PathBuf};
+use async_std::task::sleep;
+use std::collections::hash_map::{Entry, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(300);
+
+/// Build the site, then rebuild changed files forever
+///
+/// Polls the modification times of every watched path, since
+/// referenced files may be anywhere. Files newly referenced by a
+/// rebuild are watched from then on. Failures are reported without
+/// stopping, and every rebuild is followed by a call back.
+///
+pub async fn watch<F: Fn()>(site: Arc<Site>, on_rebuild: F) -> anyhow::Result<()> {
+    let start = Instant::now();
+    match site.build().await {
+        Ok(()) => println!("Built in {}ms", start.elapsed().as_millis()),
+        Err(err) => eprintln!("Error: {}", err),
+    }
+
+    let mut modified_times = ModifiedTimes::new();
+    modified_times.add(site.watched_paths()).await;
+    loop {
+        sleep(POLL_INTERVAL).await;
+
+        let changed_paths = modified_times.update().await;
+        if changed_paths.is_empty() {
+            continue;
+        }
+        for path in &changed_paths {
+            println!("Changed: {}", path.display());
+        }
+
+        let start = Instant::now();
+        let changed_count = changed_paths.len();
+        match site.rebuild(changed_paths).await {
+            Ok(()) => println!(
+                "Rebuilt {} {} in {}ms",
+                changed_count,
+                if changed_count == 1 { "file" } else { "files" },
+                start.elapsed().as_millis()
+            ),
+            Err(err) => eprintln!("Error: {}", err),
+        }
+        on_rebuild();
+        modified_times.add(site.watched_paths()).await;
+    }
+}
+
+/// Last seen modification time of each watched path
+///
+/// `None` for paths which don't exist, so their creation counts as
+/// a change too.
+///
+struct ModifiedTimes(HashMap<PathBuf, Option<SystemTime>>);
+
+impl ModifiedTimes {
+    fn new() -> Self {
+        ModifiedTimes(HashMap::new())
+    }
+
+    /// Start watching paths which aren't watched yet
+    async fn add(&mut self, paths: Vec<PathBuf>) {
+        for path in paths {
+            if let Entry::Vacant(entry) = self.0.entry(path) {
+                let modified_time = modified_time(entry.key()).await;
+                entry.insert(modified_time);
+            }
+        }
+    }
+
+    /// Check every watched path, returning those which changed
+    async fn update(&mut self) -> Vec<PathBuf> {
+        let mut changed_paths = Vec::new();
+        for (path, last_modified_time) in self.0.iter_mut() {
+            let modified_time = modified_time(path).await;
+            if modified_time != *last_modified_time {
+                *last_modified_time = modified_time;
+                changed_paths.push(path.clone());
+            }
+        }
+        changed_paths.sort();
+
+        changed_paths
+    }
+}
+
+async fn modified_time(path: &Path) -> Option<SystemTime> {
+    metadata(path).await.ok()?.modified().ok()
+}
@@ -0,0 +1,76 @@
+use anyhow::anyhow;
+use clap::{App, Arg, ArgMatches};
+use std::thread::available_parallelism;
+
+pub struct Arguments<'a> {
+    matches: ArgMatches<'a>,
+}
+
+impl<'a> Arguments<'a> {
+    pub fn parse() -> Self {
+        let matches = App::new("ffuu-html")
+            .version("0.1")
+            .author("Kevin Sullivan <kevin@sull.vn>")
+            .about("Run embeds in HTML from stdin, writing the result to stdout")
+            .arg(
+                Arg::with_name("minify")
+                    .long("minify")
+                    .help("Minify the output for production"),
+            )
+            .arg(
+                Arg::with_name("jobs")
+                    .short("j")
+                    .long("jobs")
+                    .takes_value(true)
+                    .value_name("N")
+                    .help("Number of embeds to run at once, defaulting to the number of CPUs"),
+            )
+            .arg(
+                Arg::with_name("on-embed-error")
+                    .long("on-embed-error")
+                    .takes_value(true)
+                    .value_name("POLICY")
+                    .possible_values(&["abort", "inline", "keep"])
+                    .default_value("abort")
+                    .help(
+                        "What to do when an embed fails: abort without output, leave an \
+                         inline error box, or keep the original markup",
+                    ),
+            )
+            .get_matches();
+
+        Arguments { matches }
+    }
+
+    pub fn minify(&self) -> bool {
+        self.matches.is_present("minify")
+    }
+
+    pub fn jobs(&self) -> anyhow::Result<usize> {
+        let jobs = match self.matches.value_of("jobs") {
+            Some(jobs) => jobs.parse()?,
+            None => available_parallelism().map_or(1, |jobs| jobs.get()),
+        };
+        if jobs == 0 {
+            return Err(anyhow!("Jobs must be at least 1"));
+        }
+
+        Ok(jobs)
+    }
+
+    pub fn on_embed_error(&self) -> EmbedErrorPolicy {
+        match self.matches.value_of("on-embed-error") {
+            Some("inline") => EmbedErrorPolicy::Inline,
+            Some("keep") => EmbedErrorPolicy::Keep,
+            _ => EmbedErrorPolicy::Abort,
+        }
+    }
+}
+
+/// What to do with an embed whose command fails
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmbedErrorPolicy {
+    Abort,
+    Inline,
+    Keep,
+}
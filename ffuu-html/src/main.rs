@@ -1,85 +1,160 @@
 use anyhow::anyhow;
 use async_std::io::prelude::{ReadExt, WriteExt};
-use async_std::io::{stdin, stdout};
-use html_parse::{format_html, parse_embeds, parse_html, HTMLEmbed, HTMLPart, HTMLPartOrEmbed};
-use std::io::Write;
-use std::process::{Command, Stdio};
-use std::str;
+use async_std::io::{stdin, stdout, BufWriter};
+use async_std::task::spawn_blocking;
+use exec_embed::{exec_embed, EmbedError};
+use futures::stream::{self, StreamExt};
+use html_parse::{
+    escape_text, iter_embeds, minify_html, HTMLEmbed, HTMLFormatter, HTMLPart, HTMLPartOrEmbed,
+    ParseHTMLError, Span, Tokenizer,
+};
+
+mod args;
+mod source;
+mod stream_html;
+
+use args::{Arguments, EmbedErrorPolicy};
+use stream_html::stream_html;
 
 #[async_std::main]
 async fn main() -> anyhow::Result<()> {
+    let args = Arguments::parse();
+    let policy = args.on_embed_error();
+
+    //
+    // Stream output, unless a failed embed aborts before anything is
+    // written, or minifying, which needs every part
+    //
+    if policy != EmbedErrorPolicy::Abort && !args.minify() {
+        return stream_html(args.jobs()?, policy).await;
+    }
+
     //
-    // Read
+    // Read all of the input up front
     //
     let mut input = String::new();
     stdin().read_to_string(&mut input).await?;
 
     //
-    // Parse HTML
+    // Parse HTML and embeds
     //
-    let input_parts = parse_html(&input)?;
-    let input_parts_count = input_parts.len();
+    let with_embeds: Vec<HTMLPartOrEmbed> = iter_embeds(Tokenizer::new(&input)).collect();
 
     //
-    // Parse embeds
+    // Execute embeds, a limited number at a time. Outputs come back
+    // in document order, however long each embed takes.
     //
-    let with_embeds = parse_embeds(input_parts);
+    let embeds: Vec<HTMLEmbed<'static>> = with_embeds
+        .iter()
+        .filter_map(|part_or_embed| match part_or_embed {
+            HTMLPartOrEmbed::Embed(embed) => Some(embed.clone().into_owned()),
+            HTMLPartOrEmbed::Part(_) => None,
+        })
+        .collect();
+    let embed_results: Vec<(HTMLEmbed, Result<String, EmbedError>)> = stream::iter(embeds)
+        .map(|embed| {
+            spawn_blocking(move || {
+                let result = exec_embed(&embed, None);
+                (embed, result)
+            })
+        })
+        .buffered(args.jobs()?)
+        .collect()
+        .await;
 
     //
-    // Execute embeds
+    // Report failed embeds, replacing them as the policy says
     //
-    let mut result_parts: Vec<HTMLPart> = Vec::with_capacity(input_parts_count);
-    for part_or_embed in with_embeds {
-        match part_or_embed {
-            HTMLPartOrEmbed::Part(part) => result_parts.push(part),
-            HTMLPartOrEmbed::Embed(embed) => {
-                let result = exec_embed(&embed);
-                if let Ok(output) = result {
-                    result_parts.push(HTMLPart::Text(output.into()));
-                }
+    let mut failure_count = 0;
+    let mut embed_outputs = Vec::new();
+    for (embed, result) in embed_results {
+        let err = match result {
+            Ok(embed_output) => {
+                embed_outputs.push(embed_output);
+                continue;
             }
-        }
+            Err(err) => err,
+        };
+        let location = ParseHTMLError::for_span(&input, embed.span);
+        let message = failure_message(&embed, &location, &err);
+        failure_count += 1;
+
+        embed_outputs.push(match policy {
+            EmbedErrorPolicy::Abort => {
+                eprintln!("Error: {}", message);
+                continue;
+            }
+            EmbedErrorPolicy::Inline => {
+                eprintln!("Warning: {}", message);
+                inline_error(&message)
+            }
+            EmbedErrorPolicy::Keep => {
+                eprintln!("Warning: {}", message);
+                input[embed.span.start..embed.span.end].to_owned()
+            }
+        });
     }
+    if failure_count > 0 && policy == EmbedErrorPolicy::Abort {
+        return Err(anyhow!("{} embed(s) failed", failure_count));
+    }
+    let mut embed_outputs = embed_outputs.into_iter();
 
     //
-    // Render
+    // Splice in embed outputs, rendering and writing parts, unless
+    // minifying, which needs them all
     //
-    let output = format_html(&result_parts);
+    let mut formatter = HTMLFormatter::new();
+    let mut minify_parts: Vec<HTMLPart> = Vec::new();
+    let mut output = BufWriter::new(stdout());
+    for part_or_embed in with_embeds {
+        let part = match part_or_embed {
+            HTMLPartOrEmbed::Part(HTMLPart::Bogus(bogus, span)) => {
+                eprintln!("Warning: {}", ParseHTMLError::for_span(&input, span));
+                HTMLPart::Bogus(bogus, span)
+            }
+            HTMLPartOrEmbed::Part(part) => part,
+            HTMLPartOrEmbed::Embed(_) => {
+                let embed_output = embed_outputs.next().unwrap();
+                if args.minify() {
+                    for embed_part in Tokenizer::new(&embed_output) {
+                        minify_parts.push(embed_part.into_owned());
+                    }
+                    continue;
+                }
+                HTMLPart::Text(embed_output.into(), Span::default())
+            }
+        };
 
-    //
-    // Write
-    //
-    stdout().write_all(output.as_bytes()).await?;
+        if args.minify() {
+            minify_parts.push(part);
+        } else {
+            output
+                .write_all(formatter.format_part(&part).as_bytes())
+                .await?;
+        }
+    }
+    if args.minify() {
+        output
+            .write_all(minify_html(&minify_parts).as_bytes())
+            .await?;
+    }
+    output.flush().await?;
 
     Ok(())
 }
 
-fn exec_embed(request: &HTMLEmbed) -> anyhow::Result<String> {
-    let HTMLEmbed { command, input } = request;
-    let stdin = if input.is_some() {
-        Stdio::piped()
-    } else {
-        Stdio::null()
-    };
-
-    let mut child = Command::new("sh")
-        .arg("-c")
-        .arg(command)
-        .stdin(stdin)
-        .stdout(Stdio::piped())
-        .spawn()?;
-
-    if let Some(input_text) = input {
-        child
-            .stdin
-            .as_mut()
-            .ok_or(anyhow!("Can't borrow stdin as mutable"))?
-            .write_all(input_text.as_bytes())?;
-    }
-    let output = child.wait_with_output()?;
-    let text = str::from_utf8(&output.stdout)?;
-    let trimmed = text.trim();
-    let string = trimmed.to_owned();
+/// Message for an embed which failed, located in the input
+fn failure_message(embed: &HTMLEmbed, location: &ParseHTMLError, err: &EmbedError) -> String {
+    format!(
+        "Embed `{}` at line {}, column {} failed: {}",
+        embed.command, location.line, location.column, err
+    )
+}
 
-    Ok(string)
+/// Inline error box in place of a failed embed
+fn inline_error(message: &str) -> String {
+    format!(
+        "<pre class=\"ffuu-embed-error\">{}</pre>",
+        escape_text(message)
+    )
 }
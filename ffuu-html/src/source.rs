@@ -0,0 +1,117 @@
+use html_parse::{ParseHTMLError, Span};
+use std::io::{self, BufRead, Read};
+use std::sync::{Arc, Mutex};
+
+/// Input read so far, from the start of the line of the earliest
+/// part still needed
+///
+/// Lets streamed output locate failures and keep original markup
+/// without holding on to the whole input.
+///
+#[derive(Default)]
+pub struct Source {
+    text: Vec<u8>,
+    /// Byte offset of the text within the input
+    offset: usize,
+    /// Lines of the input before the text
+    line: usize,
+    /// Length of the text known not to have a line break
+    searched_len: usize,
+}
+
+impl Source {
+    /// Forget input before a byte offset, apart from its line
+    pub fn forget_before(&mut self, end: usize) {
+        let len = end.saturating_sub(self.offset).min(self.text.len());
+        if len <= self.searched_len {
+            return;
+        }
+        let line_start = match self.text[self.searched_len..len]
+            .iter()
+            .rposition(|&byte| byte == b'\n')
+        {
+            Some(i) => self.searched_len + i + 1,
+            None => {
+                self.searched_len = len;
+                return;
+            }
+        };
+
+        self.line += self.text[..line_start]
+            .iter()
+            .filter(|&&byte| byte == b'\n')
+            .count();
+        self.text.drain(..line_start);
+        self.offset += line_start;
+        self.searched_len = len - line_start;
+    }
+
+    /// Original markup of a span which hasn't been forgotten
+    pub fn text(&self, span: Span) -> String {
+        let start = span.start - self.offset;
+        let end = (span.end - self.offset).min(self.text.len());
+
+        String::from_utf8_lossy(&self.text[start..end]).into_owned()
+    }
+
+    /// Error for a span which hasn't been forgotten, located as if
+    /// the whole input were at hand
+    pub fn error(&self, span: Span) -> ParseHTMLError {
+        let text = String::from_utf8_lossy(&self.text);
+        let relative_span = Span::new(span.start - self.offset, span.end - self.offset);
+        let error = ParseHTMLError::for_span(&text, relative_span);
+
+        ParseHTMLError {
+            span,
+            line: error.line + self.line,
+            ..error
+        }
+    }
+}
+
+/// Reader which records everything read through it into a source
+pub struct RecordingReader<R> {
+    reader: R,
+    source: Arc<Mutex<Source>>,
+}
+
+impl<R> RecordingReader<R> {
+    pub fn new(reader: R, source: Arc<Mutex<Source>>) -> Self {
+        RecordingReader { reader, source }
+    }
+}
+
+impl<R: BufRead> Read for RecordingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = {
+            let available = self.fill_buf()?;
+            let len = available.len().min(buf.len());
+            buf[..len].copy_from_slice(&available[..len]);
+            len
+        };
+        self.consume(len);
+
+        Ok(len)
+    }
+}
+
+impl<R: BufRead> BufRead for RecordingReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.reader.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        // Filling again only returns what's already buffered
+        if amt > 0 {
+            if let Ok(buffer) = self.reader.fill_buf() {
+                let len = amt.min(buffer.len());
+                self.source
+                    .lock()
+                    .unwrap()
+                    .text
+                    .extend_from_slice(&buffer[..len]);
+            }
+        }
+        self.reader.consume(amt);
+    }
+}
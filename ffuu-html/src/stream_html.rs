@@ -0,0 +1,146 @@
+use async_std::channel;
+use async_std::io::prelude::WriteExt;
+use async_std::io::{stdout, BufWriter};
+use async_std::task::{spawn_blocking, JoinHandle};
+use exec_embed::{exec_embed, EmbedError};
+use futures::future::{select, Either};
+use html_parse::{
+    iter_embeds, tokenize_reader, HTMLEmbed, HTMLFormatter, HTMLPart, HTMLPartOrEmbed, Span,
+};
+use std::collections::VecDeque;
+use std::io;
+use std::sync::mpsc::sync_channel;
+use std::sync::{Arc, Mutex};
+
+use crate::args::EmbedErrorPolicy;
+use crate::source::{RecordingReader, Source};
+use crate::{failure_message, inline_error};
+
+/// Parts parsed ahead of the output, per stage
+const LOOKAHEAD: usize = 64;
+
+/// Parts waiting on an embed before them to be written
+enum Pending {
+    Part(HTMLPart<'static>),
+    Embed(HTMLEmbed<'static>, JoinHandle<Result<String, EmbedError>>),
+}
+
+enum Next {
+    Item(Option<HTMLPartOrEmbed<'static>>),
+    EmbedResult(Result<String, EmbedError>),
+}
+
+/// Run embeds in HTML from stdin, writing each part to stdout as soon
+/// as everything before it is written
+///
+/// Stdin is tokenized on one thread and embeds are found on another,
+/// while up to `jobs` embeds run at once. Failed embeds are replaced
+/// as the policy says, which mustn't be to abort, since output before
+/// them is already written.
+///
+pub async fn stream_html(jobs: usize, policy: EmbedErrorPolicy) -> anyhow::Result<()> {
+    let source = Arc::new(Mutex::new(Source::default()));
+    let (parts_sender, parts) = sync_channel(LOOKAHEAD);
+    let reader_source = source.clone();
+    let tokenizing = spawn_blocking(move || {
+        let reader = RecordingReader::new(io::stdin().lock(), reader_source);
+        tokenize_reader(reader, |part| {
+            parts_sender
+                .send(part.into_owned())
+                .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+        })
+    });
+    let (items_sender, items) = channel::bounded(LOOKAHEAD);
+    spawn_blocking(move || {
+        for item in iter_embeds(parts) {
+            if items_sender.send_blocking(item).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut formatter = HTMLFormatter::new();
+    let mut output = BufWriter::new(stdout());
+    let mut pending: VecDeque<Pending> = VecDeque::new();
+    let mut running_count = 0;
+    let mut is_input_done = false;
+    loop {
+        while let Some(Pending::Part(_)) = pending.front() {
+            if let Some(Pending::Part(part)) = pending.pop_front() {
+                if let HTMLPart::Bogus(_, span) = part {
+                    eprintln!("Warning: {}", source.lock().unwrap().error(span));
+                }
+                output
+                    .write_all(formatter.format_part(&part).as_bytes())
+                    .await?;
+                source.lock().unwrap().forget_before(part.span().end);
+            }
+        }
+        if is_input_done && pending.is_empty() {
+            break;
+        }
+
+        // Whatever's written so far shouldn't wait on what's next
+        output.flush().await?;
+        let can_receive = !is_input_done && running_count < jobs;
+        let next = match pending.front_mut() {
+            Some(Pending::Embed(_, running)) if can_receive => {
+                match select(Box::pin(items.recv()), running).await {
+                    Either::Left((item, _)) => Next::Item(item.ok()),
+                    Either::Right((result, _)) => Next::EmbedResult(result),
+                }
+            }
+            Some(Pending::Embed(_, running)) => Next::EmbedResult(running.await),
+            _ => Next::Item(items.recv().await.ok()),
+        };
+
+        match next {
+            Next::Item(Some(HTMLPartOrEmbed::Part(part))) => pending.push_back(Pending::Part(part)),
+            Next::Item(Some(HTMLPartOrEmbed::Embed(embed))) => {
+                let request = embed.clone();
+                let running = spawn_blocking(move || exec_embed(&request, None));
+                running_count += 1;
+                pending.push_back(Pending::Embed(embed, running));
+            }
+            Next::Item(None) => is_input_done = true,
+            Next::EmbedResult(result) => {
+                let embed = match pending.pop_front() {
+                    Some(Pending::Embed(embed, _)) => embed,
+                    _ => unreachable!("Only the first pending embed is awaited"),
+                };
+                running_count -= 1;
+
+                let embed_output = match result {
+                    Ok(embed_output) => embed_output,
+                    Err(err) => replace_failed(&source.lock().unwrap(), &embed, &err, policy),
+                };
+                let part = HTMLPart::Text(embed_output.into(), Span::default());
+                output
+                    .write_all(formatter.format_part(&part).as_bytes())
+                    .await?;
+                source.lock().unwrap().forget_before(embed.span.end);
+            }
+        }
+    }
+    output.flush().await?;
+    tokenizing.await?;
+
+    Ok(())
+}
+
+/// Output in place of a failed embed, warning about it
+fn replace_failed(
+    source: &Source,
+    embed: &HTMLEmbed,
+    err: &EmbedError,
+    policy: EmbedErrorPolicy,
+) -> String {
+    let message = failure_message(embed, &source.error(embed.span), err);
+    eprintln!("Warning: {}", message);
+
+    match policy {
+        EmbedErrorPolicy::Inline => inline_error(&message),
+        EmbedErrorPolicy::Keep => source.text(embed.span),
+        EmbedErrorPolicy::Abort => unreachable!("Aborting needs all embeds run before output"),
+    }
+}
@@ -7,6 +7,7 @@ use async_std::task::spawn;
 use futures::future;
 use html_parse::{parse_tag, DepthChange, HTMLTag, HTMLTagKind, STANDARD_HTML_ELEMENTS};
 use pulldown_cmark::{html, CowStr, Event, Parser};
+use std::borrow::Cow;
 use std::env;
 use std::io::Write;
 use std::ops::Range;
@@ -30,12 +31,9 @@ async fn main() -> anyhow::Result<()> {
     let mut tasks = Vec::new();
     let mut entries = read_dir(input_path).await?;
     while let Some(entry) = entries.next().await {
-        match entry {
-            Ok(entry) => {
-                let handle = spawn(write_html(entry.path(), output_path.clone()));
-                tasks.push(handle);
-            }
-            _ => {}
+        if let Ok(entry) = entry {
+            let handle = spawn(write_html(entry.path(), output_path.clone()));
+            tasks.push(handle);
         };
     }
 
@@ -90,7 +88,7 @@ fn do_embed_request(request: &EmbedRequest) -> anyhow::Result<String> {
 
 fn embed_result_piece<'a>(result: &'a anyhow::Result<String>) -> Piece<'a> {
     match result {
-        Ok(embed_text) => Piece::EmbedResult(Parser::new(embed_text.trim())),
+        Ok(embed_text) => Piece::EmbedResult(Box::new(Parser::new(embed_text.trim()))),
         Err(_) => Piece::EmbedError,
     }
 }
@@ -99,13 +97,13 @@ fn embed_result_piece<'a>(result: &'a anyhow::Result<String>) -> Piece<'a> {
 enum EmbedParsing<'a> {
     None,
     Start {
-        executable: &'a str,
-        args: Option<&'a str>,
+        executable: Cow<'a, str>,
+        args: Option<Cow<'a, str>>,
         depth: isize,
     },
     Partial {
-        executable: &'a str,
-        args: Option<&'a str>,
+        executable: Cow<'a, str>,
+        args: Option<Cow<'a, str>>,
         depth: isize,
         range: Range<usize>,
     },
@@ -113,16 +111,16 @@ enum EmbedParsing<'a> {
 
 #[derive(Debug)]
 struct EmbedRequest<'a> {
-    executable: &'a str,
+    executable: Cow<'a, str>,
     input: &'a str,
-    args: Option<&'a str>,
+    args: Option<Cow<'a, str>>,
     piece_index: usize,
 }
 
 enum Piece<'a> {
     Markdown(Event<'a>),
     EmbedPending,
-    EmbedResult(Parser<'a>),
+    EmbedResult(Box<Parser<'a>>),
     EmbedError,
 }
 
@@ -159,7 +157,7 @@ impl<'a> Iterator for EmbarkdownParser<'a> {
         match p {
             Piece::Markdown(m) => Some(m),
             Piece::EmbedResult(parser) => {
-                self.embed_result_parser = Some(parser);
+                self.embed_result_parser = Some(*parser);
                 self.next()
             }
             Piece::EmbedPending | Piece::EmbedError => panic!("Parsing error"),
@@ -174,8 +172,8 @@ fn exec_embed(request: &EmbedRequest) -> anyhow::Result<Vec<u8>> {
         input,
         ..
     } = request;
-    let split_args = maybe_args.unwrap_or("").split(' ');
-    let mut child = Command::new(executable)
+    let split_args = maybe_args.as_deref().unwrap_or("").split(' ');
+    let mut child = Command::new(executable.as_ref())
         .args(split_args)
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
@@ -197,10 +195,10 @@ fn do_parse<'a>(text: &'a str) -> (Vec<Piece<'a>>, Vec<EmbedRequest<'a>>) {
     let mut pieces: Vec<Piece> = Vec::new();
     let mut embed_requests: Vec<EmbedRequest> = Vec::new();
 
-    let mut md_offset_events = Parser::new(text).into_offset_iter();
-    while let Some((event, range)) = md_offset_events.next() {
+    let md_offset_events = Parser::new(text).into_offset_iter();
+    for (event, range) in md_offset_events {
         let html_tag = if let Event::Html(CowStr::Borrowed(tag)) = event {
-            parse_tag(&tag).map(|(_input, tag)| tag).ok()
+            parse_tag(tag).map(|(_input, tag)| tag).ok()
         } else {
             None
         };
@@ -217,16 +215,16 @@ fn do_parse<'a>(text: &'a str) -> (Vec<Piece<'a>>, Vec<EmbedRequest<'a>>) {
                     kind: HTMLTagKind::Open,
                     name,
                     attributes,
+                    ..
                 }),
                 EmbedParsing::None,
-            ) if STANDARD_HTML_ELEMENTS.get(name).is_none() => {
+            ) if STANDARD_HTML_ELEMENTS.get(name.as_ref()).is_none() => {
                 let args = attributes
-                    .into_iter()
-                    .find(|(attr_name, _)| attr_name == &"args")
-                    .map(|(_, args_value)| *args_value)
-                    .flatten();
+                    .iter()
+                    .find(|(attr_name, _)| attr_name == "args")
+                    .and_then(|(_, args_value)| args_value.clone());
                 embed_request = EmbedParsing::Start {
-                    executable: name,
+                    executable: name.clone(),
                     args,
                     depth,
                 };
@@ -254,8 +252,8 @@ fn do_parse<'a>(text: &'a str) -> (Vec<Piece<'a>>, Vec<EmbedRequest<'a>>) {
                 },
             ) if depth == *embed_depth + 1 => {
                 embed_requests.push(EmbedRequest {
-                    executable,
-                    args: *args,
+                    executable: executable.clone(),
+                    args: args.clone(),
                     input: &text[*start..*end],
                     piece_index: pieces.len(),
                 });
@@ -272,8 +270,8 @@ fn do_parse<'a>(text: &'a str) -> (Vec<Piece<'a>>, Vec<EmbedRequest<'a>>) {
                 },
             ) => {
                 embed_request = EmbedParsing::Partial {
-                    executable,
-                    args: *args,
+                    executable: executable.clone(),
+                    args: args.clone(),
                     range,
                     depth: *depth,
                 };
@@ -1,11 +1,35 @@
-use crate::depth::WithDepthIterator;
-use crate::{format_html, HTMLPart, HTMLTag, HTMLTagKind};
+use crate::depth::{DepthIterator, WithDepthIterator};
+use crate::types::into_owned;
+use crate::{format_html, HTMLPart, HTMLTag, HTMLTagKind, Span};
 use std::borrow::Cow;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct HTMLEmbed<'a> {
-    pub command: &'a str,
+    /// Decoded `command` attribute, ready for the shell
+    pub command: Cow<'a, str>,
     pub input: Option<Cow<'a, str>>,
+    /// Opening `run` tag, for its other attributes and location
+    pub tag: HTMLTag<'a>,
+    /// Whole embed, from its opening tag through its closing tag
+    pub span: Span,
+}
+
+impl<'a> HTMLEmbed<'a> {
+    /// Decoded value of an attribute of the `run` tag
+    pub fn attribute_value(&self, name: &str) -> Option<Cow<'_, str>> {
+        self.tag.attribute_value(name)
+    }
+
+    /// Copy anything borrowed from the input, so the embed can
+    /// outlive it
+    pub fn into_owned(self) -> HTMLEmbed<'static> {
+        HTMLEmbed {
+            command: into_owned(self.command),
+            input: self.input.map(into_owned),
+            tag: self.tag.into_owned(),
+            span: self.span,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -14,22 +38,30 @@ pub enum HTMLPartOrEmbed<'a> {
     Embed(HTMLEmbed<'a>),
 }
 
+impl<'a> HTMLPartOrEmbed<'a> {
+    /// Copy anything borrowed from the input, so this can outlive it
+    pub fn into_owned(self) -> HTMLPartOrEmbed<'static> {
+        match self {
+            HTMLPartOrEmbed::Part(part) => HTMLPartOrEmbed::Part(part.into_owned()),
+            HTMLPartOrEmbed::Embed(embed) => HTMLPartOrEmbed::Embed(embed.into_owned()),
+        }
+    }
+}
+
 impl<'a> From<&HTMLPart<'a>> for Option<HTMLEmbed<'a>> {
     fn from(part: &HTMLPart<'a>) -> Self {
         match part {
-            HTMLPart::Tag(HTMLTag {
-                name: "run",
-                kind,
-                attributes,
-            }) if *kind == HTMLTagKind::Open || *kind == HTMLTagKind::Void => attributes
-                .iter()
-                .find(|(k, _)| *k == "command")
-                .map(|(_, v)| *v)
-                .flatten()
-                .map(|command| HTMLEmbed {
-                    command,
+            HTMLPart::Tag(tag)
+                if tag.name == "run"
+                    && (tag.kind == HTMLTagKind::Open || tag.kind == HTMLTagKind::Void) =>
+            {
+                tag.attribute_value("command").map(|command| HTMLEmbed {
+                    command: command.into_owned().into(),
                     input: None,
-                }),
+                    tag: tag.clone(),
+                    span: tag.span,
+                })
+            }
             _ => None,
         }
     }
@@ -37,146 +69,284 @@ impl<'a> From<&HTMLPart<'a>> for Option<HTMLEmbed<'a>> {
 
 #[derive(Debug)]
 struct PendingHTMLEmbed<'a> {
-    command: &'a str,
+    command: Cow<'a, str>,
+    tag: HTMLTag<'a>,
     depth: isize,
     input_parts: Vec<HTMLPart<'a>>,
 }
 
-#[allow(dead_code)]
 pub fn parse_embeds<'a>(html_parts: Vec<HTMLPart<'a>>) -> Vec<HTMLPartOrEmbed<'a>> {
-    let mut html_parts_or_embeds = Vec::new();
-    let mut maybe_pending_embed: Option<PendingHTMLEmbed<'a>> = None;
-
-    for (hp, depth) in html_parts.into_iter().with_depth() {
-        let maybe_new_embed: Option<HTMLEmbed<'a>> = (&hp).into();
-        let is_embed_end: bool = match (&maybe_pending_embed, &hp) {
-            (
-                Some(PendingHTMLEmbed {
-                    depth: pe_depth, ..
-                }),
-                HTMLPart::Tag(HTMLTag {
-                    name: "run",
-                    kind: HTMLTagKind::Close,
-                    ..
-                }),
-            ) if depth <= *pe_depth => true,
-            _ => false,
-        };
+    iter_embeds(html_parts).collect()
+}
 
-        match (&mut maybe_pending_embed, maybe_new_embed, &hp, is_embed_end) {
-            (
-                None,
-                Some(new_embed),
-                &HTMLPart::Tag(HTMLTag {
-                    kind: HTMLTagKind::Void,
-                    ..
-                }),
-                false,
-            ) => html_parts_or_embeds.push(HTMLPartOrEmbed::Embed(HTMLEmbed {
-                command: new_embed.command,
-                input: None,
-            })),
-            (
-                None,
-                Some(new_embed),
-                &HTMLPart::Tag(HTMLTag {
-                    kind: HTMLTagKind::Open,
-                    ..
-                }),
-                false,
-            ) => {
-                maybe_pending_embed = Some(PendingHTMLEmbed {
-                    command: new_embed.command,
-                    depth,
-                    input_parts: Vec::new(),
-                })
-            }
-            (Some(pending_embed), _, _, false) => pending_embed.input_parts.push(hp),
-            (Some(finished_embed), None, _, true) => {
-                let input_formatted = format_html(&finished_embed.input_parts);
-                html_parts_or_embeds.push(HTMLPartOrEmbed::Embed(HTMLEmbed {
-                    command: finished_embed.command,
-                    input: Some(input_formatted.into()),
-                }));
-                maybe_pending_embed = None;
-            }
-            _ => html_parts_or_embeds.push(HTMLPartOrEmbed::Part(hp)),
-        };
+/// Parse embeds lazily
+///
+/// Parts are passed through as they come, except for those within
+/// an embed, which are held until it ends.
+///
+pub fn iter_embeds<'a, T>(html_parts: T) -> Embeds<'a, T::IntoIter>
+where
+    T: IntoIterator<Item = HTMLPart<'a>>,
+{
+    Embeds {
+        html_parts: html_parts.into_iter().with_depth(),
+        maybe_pending_embed: None,
     }
+}
+
+/// Iterator over parts and embeds, from `iter_embeds`
+pub struct Embeds<'a, I: Iterator<Item = HTMLPart<'a>>> {
+    html_parts: DepthIterator<HTMLPart<'a>, I>,
+    maybe_pending_embed: Option<PendingHTMLEmbed<'a>>,
+}
 
-    html_parts_or_embeds
+impl<'a, I: Iterator<Item = HTMLPart<'a>>> Iterator for Embeds<'a, I> {
+    type Item = HTMLPartOrEmbed<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for (hp, depth) in &mut self.html_parts {
+            let maybe_new_embed: Option<HTMLEmbed<'a>> = (&hp).into();
+            let is_embed_end: bool = matches!(
+                (&self.maybe_pending_embed, &hp),
+                (
+                    Some(PendingHTMLEmbed {
+                        depth: pe_depth, ..
+                    }),
+                    HTMLPart::Tag(HTMLTag {
+                        name,
+                        kind: HTMLTagKind::Close,
+                        ..
+                    }),
+                ) if name == "run" && depth <= *pe_depth
+            );
+
+            match (
+                &mut self.maybe_pending_embed,
+                maybe_new_embed,
+                &hp,
+                is_embed_end,
+            ) {
+                (
+                    None,
+                    Some(new_embed),
+                    &HTMLPart::Tag(HTMLTag {
+                        kind: HTMLTagKind::Void,
+                        ..
+                    }),
+                    false,
+                ) => return Some(HTMLPartOrEmbed::Embed(new_embed)),
+                (
+                    None,
+                    Some(new_embed),
+                    &HTMLPart::Tag(HTMLTag {
+                        kind: HTMLTagKind::Open,
+                        ..
+                    }),
+                    false,
+                ) => {
+                    self.maybe_pending_embed = Some(PendingHTMLEmbed {
+                        command: new_embed.command,
+                        tag: new_embed.tag,
+                        depth,
+                        input_parts: Vec::new(),
+                    })
+                }
+                (Some(pending_embed), _, _, false) => pending_embed.input_parts.push(hp),
+                (Some(finished_embed), None, _, true) => {
+                    let input_formatted = format_html(&finished_embed.input_parts);
+                    let PendingHTMLEmbed { command, tag, .. } =
+                        self.maybe_pending_embed.take().unwrap();
+
+                    return Some(HTMLPartOrEmbed::Embed(HTMLEmbed {
+                        command,
+                        input: Some(input_formatted.into()),
+                        span: Span::new(tag.span.start, hp.span().end),
+                        tag,
+                    }));
+                }
+                _ => return Some(HTMLPartOrEmbed::Part(hp)),
+            };
+        }
+
+        None
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::Span;
+
+    fn run_tag(kind: HTMLTagKind, command: &str) -> HTMLTag<'_> {
+        HTMLTag {
+            name: "run".into(),
+            kind,
+            attributes: vec![("command".into(), Some(command.into()))],
+            span: Span::default(),
+        }
+    }
 
     #[test]
     fn parse_no_embed() {
         assert_eq!(
             parse_embeds(vec![
                 HTMLPart::Tag(HTMLTag {
-                    name: "p",
+                    name: "p".into(),
                     kind: HTMLTagKind::Open,
                     attributes: Vec::new(),
+                    span: Span::default(),
                 }),
-                HTMLPart::Text("Paragraph text.".into()),
+                HTMLPart::Text("Paragraph text.".into(), Span::default()),
                 HTMLPart::Tag(HTMLTag {
-                    name: "p",
+                    name: "p".into(),
                     kind: HTMLTagKind::Close,
                     attributes: Vec::new(),
+                    span: Span::default(),
                 }),
             ]),
             vec![
                 HTMLPartOrEmbed::Part(HTMLPart::Tag(HTMLTag {
-                    name: "p",
+                    name: "p".into(),
                     kind: HTMLTagKind::Open,
                     attributes: Vec::new(),
+                    span: Span::default(),
                 })),
-                HTMLPartOrEmbed::Part(HTMLPart::Text("Paragraph text.".into())),
+                HTMLPartOrEmbed::Part(HTMLPart::Text("Paragraph text.".into(), Span::default())),
                 HTMLPartOrEmbed::Part(HTMLPart::Tag(HTMLTag {
-                    name: "p",
+                    name: "p".into(),
                     kind: HTMLTagKind::Close,
                     attributes: Vec::new(),
+                    span: Span::default(),
                 }))
             ]
         )
     }
 
+    #[test]
+    fn parse_custom_element_not_embed() {
+        assert_eq!(
+            parse_embeds(vec![HTMLPart::Tag(HTMLTag {
+                name: "my-widget".into(),
+                kind: HTMLTagKind::Void,
+                attributes: vec![
+                    ("data-id".into(), Some("3".into())),
+                    ("@click".into(), Some("go".into()))
+                ],
+                span: Span::default(),
+            })]),
+            vec![HTMLPartOrEmbed::Part(HTMLPart::Tag(HTMLTag {
+                name: "my-widget".into(),
+                kind: HTMLTagKind::Void,
+                attributes: vec![
+                    ("data-id".into(), Some("3".into())),
+                    ("@click".into(), Some("go".into()))
+                ],
+                span: Span::default(),
+            }))]
+        )
+    }
+
+    #[test]
+    fn parse_embed_with_custom_element_input() {
+        assert_eq!(
+            parse_embeds(vec![
+                HTMLPart::Tag(HTMLTag {
+                    name: "run".into(),
+                    kind: HTMLTagKind::Open,
+                    attributes: vec![
+                        ("command".into(), Some("cat".into())),
+                        ("data-id".into(), Some("3".into()))
+                    ],
+                    span: Span::default(),
+                }),
+                HTMLPart::Tag(HTMLTag {
+                    name: "my-widget".into(),
+                    kind: HTMLTagKind::Open,
+                    attributes: vec![("xlink:href".into(), Some("#a".into()))],
+                    span: Span::default(),
+                }),
+                HTMLPart::Tag(HTMLTag {
+                    name: "my-widget".into(),
+                    kind: HTMLTagKind::Close,
+                    attributes: Vec::new(),
+                    span: Span::default(),
+                }),
+                HTMLPart::Tag(HTMLTag {
+                    name: "run".into(),
+                    kind: HTMLTagKind::Close,
+                    attributes: Vec::new(),
+                    span: Span::default(),
+                }),
+            ]),
+            vec![HTMLPartOrEmbed::Embed(HTMLEmbed {
+                command: "cat".into(),
+                input: Some("<my-widget xlink:href=\"#a\"></my-widget>".into()),
+                tag: HTMLTag {
+                    name: "run".into(),
+                    kind: HTMLTagKind::Open,
+                    attributes: vec![
+                        ("command".into(), Some("cat".into())),
+                        ("data-id".into(), Some("3".into()))
+                    ],
+                    span: Span::default(),
+                },
+                span: Span::default(),
+            })]
+        )
+    }
+
     #[test]
     fn parse_embed_without_input() {
         assert_eq!(
             parse_embeds(vec![HTMLPart::Tag(HTMLTag {
-                name: "run",
+                name: "run".into(),
                 kind: HTMLTagKind::Void,
-                attributes: vec![("command", Some("date"))],
+                attributes: vec![("command".into(), Some("date".into()))],
+                span: Span::default(),
             })]),
             vec![HTMLPartOrEmbed::Embed(HTMLEmbed {
-                command: "date",
+                command: "date".into(),
                 input: None,
+                tag: run_tag(HTMLTagKind::Void, "date"),
+                span: Span::default(),
             })]
         )
     }
 
+    #[test]
+    fn parse_embed_decodes_command() {
+        let tag = run_tag(HTMLTagKind::Void, "cat a &amp;&amp; echo &quot;b&quot;");
+
+        assert!(matches!(
+            parse_embeds(vec![HTMLPart::Tag(tag)]).as_slice(),
+            [HTMLPartOrEmbed::Embed(HTMLEmbed { command, .. })]
+                if command == "cat a && echo \"b\""
+        ));
+    }
+
     #[test]
     fn parse_embed_with_input() {
         assert_eq!(
             parse_embeds(vec![
                 HTMLPart::Tag(HTMLTag {
-                    name: "run",
+                    name: "run".into(),
                     kind: HTMLTagKind::Open,
-                    attributes: vec![("command", Some("jq ."))],
+                    attributes: vec![("command".into(), Some("jq .".into()))],
+                    span: Span::default(),
                 }),
-                HTMLPart::Text("{\"number\": 42}".into()),
+                HTMLPart::Text("{\"number\": 42}".into(), Span::default()),
                 HTMLPart::Tag(HTMLTag {
-                    name: "run",
+                    name: "run".into(),
                     kind: HTMLTagKind::Close,
                     attributes: Vec::new(),
+                    span: Span::default(),
                 }),
             ]),
             vec![HTMLPartOrEmbed::Embed(HTMLEmbed {
-                command: "jq .",
+                command: "jq .".into(),
                 input: Some("{\"number\": 42}".into()),
+                tag: run_tag(HTMLTagKind::Open, "jq ."),
+                span: Span::default(),
             }),]
         )
     }
@@ -186,35 +356,42 @@ mod tests {
         assert_eq!(
             parse_embeds(vec![
                 HTMLPart::Tag(HTMLTag {
-                    name: "div",
+                    name: "div".into(),
                     kind: HTMLTagKind::Open,
                     attributes: Vec::new(),
+                    span: Span::default(),
                 }),
                 HTMLPart::Tag(HTMLTag {
-                    name: "run",
+                    name: "run".into(),
                     kind: HTMLTagKind::Void,
-                    attributes: vec![("command", Some("date"))],
+                    attributes: vec![("command".into(), Some("date".into()))],
+                    span: Span::default(),
                 }),
                 HTMLPart::Tag(HTMLTag {
-                    name: "div",
+                    name: "div".into(),
                     kind: HTMLTagKind::Close,
                     attributes: Vec::new(),
+                    span: Span::default(),
                 }),
             ]),
             vec![
                 HTMLPartOrEmbed::Part(HTMLPart::Tag(HTMLTag {
-                    name: "div",
+                    name: "div".into(),
                     kind: HTMLTagKind::Open,
                     attributes: Vec::new(),
+                    span: Span::default(),
                 })),
                 HTMLPartOrEmbed::Embed(HTMLEmbed {
-                    command: "date",
+                    command: "date".into(),
                     input: None,
+                    tag: run_tag(HTMLTagKind::Void, "date"),
+                    span: Span::default(),
                 }),
                 HTMLPartOrEmbed::Part(HTMLPart::Tag(HTMLTag {
-                    name: "div",
+                    name: "div".into(),
                     kind: HTMLTagKind::Close,
                     attributes: Vec::new(),
+                    span: Span::default(),
                 })),
             ]
         )
@@ -225,41 +402,49 @@ mod tests {
         assert_eq!(
             parse_embeds(vec![
                 HTMLPart::Tag(HTMLTag {
-                    name: "div",
+                    name: "div".into(),
                     kind: HTMLTagKind::Open,
                     attributes: Vec::new(),
+                    span: Span::default(),
                 }),
                 HTMLPart::Tag(HTMLTag {
-                    name: "run",
+                    name: "run".into(),
                     kind: HTMLTagKind::Open,
-                    attributes: vec![("command", Some("jq ."))],
+                    attributes: vec![("command".into(), Some("jq .".into()))],
+                    span: Span::default(),
                 }),
-                HTMLPart::Text("{\"number\": 42}".into()),
+                HTMLPart::Text("{\"number\": 42}".into(), Span::default()),
                 HTMLPart::Tag(HTMLTag {
-                    name: "run",
+                    name: "run".into(),
                     kind: HTMLTagKind::Close,
                     attributes: Vec::new(),
+                    span: Span::default(),
                 }),
                 HTMLPart::Tag(HTMLTag {
-                    name: "div",
+                    name: "div".into(),
                     kind: HTMLTagKind::Close,
                     attributes: Vec::new(),
+                    span: Span::default(),
                 }),
             ]),
             vec![
                 HTMLPartOrEmbed::Part(HTMLPart::Tag(HTMLTag {
-                    name: "div",
+                    name: "div".into(),
                     kind: HTMLTagKind::Open,
                     attributes: Vec::new(),
+                    span: Span::default(),
                 })),
                 HTMLPartOrEmbed::Embed(HTMLEmbed {
-                    command: "jq .",
+                    command: "jq .".into(),
                     input: Some("{\"number\": 42}".into()),
+                    tag: run_tag(HTMLTagKind::Open, "jq ."),
+                    span: Span::default(),
                 }),
                 HTMLPartOrEmbed::Part(HTMLPart::Tag(HTMLTag {
-                    name: "div",
+                    name: "div".into(),
                     kind: HTMLTagKind::Close,
                     attributes: Vec::new(),
+                    span: Span::default(),
                 })),
             ]
         )
@@ -276,31 +461,51 @@ mod tests {
         assert_eq!(
             parse_embeds(vec![
                 HTMLPart::Tag(HTMLTag {
-                    name: "run",
+                    name: "run".into(),
                     kind: HTMLTagKind::Open,
-                    attributes: vec![("command", Some("wc -l"))],
+                    attributes: vec![("command".into(), Some("wc -l".into()))],
+                    span: Span::default(),
                 }),
                 HTMLPart::Tag(HTMLTag {
-                    name: "run",
+                    name: "run".into(),
                     kind: HTMLTagKind::Open,
-                    attributes: vec![("command", Some("jq ."))],
+                    attributes: vec![("command".into(), Some("jq .".into()))],
+                    span: Span::default(),
                 }),
-                HTMLPart::Text("{\"number\": 42}".into()),
+                HTMLPart::Text("{\"number\": 42}".into(), Span::default()),
                 HTMLPart::Tag(HTMLTag {
-                    name: "run",
+                    name: "run".into(),
                     kind: HTMLTagKind::Close,
                     attributes: Vec::new(),
+                    span: Span::default(),
                 }),
                 HTMLPart::Tag(HTMLTag {
-                    name: "run",
+                    name: "run".into(),
                     kind: HTMLTagKind::Close,
                     attributes: Vec::new(),
+                    span: Span::default(),
                 }),
             ]),
             vec![HTMLPartOrEmbed::Embed(HTMLEmbed {
-                command: "wc -l",
+                command: "wc -l".into(),
                 input: Some("<run command=\"jq .\">{\"number\": 42}</run>".into()),
+                tag: run_tag(HTMLTagKind::Open, "wc -l"),
+                span: Span::default(),
             }),]
         )
     }
+
+    #[test]
+    fn embed_spans() {
+        let html = "<p><run command=\"jq .\">{}</run><run command=\"date\" /></p>";
+        let spans: Vec<Span> = parse_embeds(crate::parse_html(html).unwrap())
+            .into_iter()
+            .filter_map(|part_or_embed| match part_or_embed {
+                HTMLPartOrEmbed::Embed(embed) => Some(embed.span),
+                HTMLPartOrEmbed::Part(_) => None,
+            })
+            .collect();
+
+        assert_eq!(spans, vec![Span::new(3, 31), Span::new(31, 53)]);
+    }
 }
@@ -0,0 +1,269 @@
+use crate::entities::NAMED_CHARACTER_REFERENCES;
+use std::borrow::Cow;
+
+/// Longest named character reference, including the `;`
+const MAX_NAMED_REFERENCE_LEN: usize = 32;
+
+/// Decode character references in text
+///
+/// Handles named references like `&amp;` plus decimal and hex
+/// numeric ones like `&#39;` and `&#x27;`. Anything which isn't a
+/// reference is left as is.
+///
+/// Spec: https://html.spec.whatwg.org/multipage/parsing.html#character-reference-state
+///
+pub fn decode_text(text: &str) -> Cow<'_, str> {
+    decode(text, false)
+}
+
+/// Decode character references in an attribute value
+///
+/// Same as `decode_text`, except legacy references without a `;`
+/// aren't decoded when followed by `=` or an alphanumeric, so URLs
+/// like `?a=1&copy=2` keep their query.
+///
+pub fn decode_attribute_value(value: &str) -> Cow<'_, str> {
+    decode(value, true)
+}
+
+/// Escape text for use as HTML text content
+pub fn escape_text(text: &str) -> Cow<'_, str> {
+    escape(text, |c| match c {
+        '&' => Some("&amp;"),
+        '<' => Some("&lt;"),
+        '>' => Some("&gt;"),
+        _ => None,
+    })
+}
+
+/// Escape text for use as a double quoted attribute value
+pub fn escape_attribute_value(value: &str) -> Cow<'_, str> {
+    escape(value, |c| match c {
+        '&' => Some("&amp;"),
+        '"' => Some("&quot;"),
+        _ => None,
+    })
+}
+
+/// Escape double quotes only
+///
+/// For attribute values which are still in their source form, with
+/// character references intact, but may have come from a single
+/// quoted attribute.
+///
+pub(crate) fn escape_quotes(value: &str) -> Cow<'_, str> {
+    escape(value, |c| match c {
+        '"' => Some("&quot;"),
+        _ => None,
+    })
+}
+
+fn escape<F: Fn(char) -> Option<&'static str>>(text: &str, replacement: F) -> Cow<'_, str> {
+    if !text.chars().any(|c| replacement(c).is_some()) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match replacement(c) {
+            Some(r) => escaped.push_str(r),
+            None => escaped.push(c),
+        }
+    }
+
+    Cow::Owned(escaped)
+}
+
+fn decode(text: &str, in_attribute: bool) -> Cow<'_, str> {
+    if !text.contains('&') {
+        return Cow::Borrowed(text);
+    }
+
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(i) = rest.find('&') {
+        decoded.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+
+        let reference = match rest.strip_prefix('#') {
+            Some(numeric) => {
+                decode_numeric(numeric).map(|(c, len)| (Cow::Owned(c.to_string()), len + 1))
+            }
+            None => decode_named(rest, in_attribute).map(|(s, len)| (Cow::Borrowed(s), len)),
+        };
+
+        match reference {
+            Some((value, len)) => {
+                decoded.push_str(&value);
+                rest = &rest[len..];
+            }
+            None => decoded.push('&'),
+        }
+    }
+    decoded.push_str(rest);
+
+    Cow::Owned(decoded)
+}
+
+/// Decode a named reference following `&`
+///
+/// Returns the replacement and the length consumed.
+///
+fn decode_named(input: &str, in_attribute: bool) -> Option<(&'static str, usize)> {
+    let name_len = input
+        .find(|c: char| !c.is_ascii_alphanumeric())
+        .unwrap_or(input.len())
+        .min(MAX_NAMED_REFERENCE_LEN);
+
+    if input[name_len..].starts_with(';') {
+        if let Some(value) = lookup_named(&input[..=name_len]) {
+            return Some((value, name_len + 1));
+        }
+    }
+
+    let (value, len) = (1..=name_len)
+        .rev()
+        .find_map(|len| lookup_named(&input[..len]).map(|value| (value, len)))?;
+    let next = input[len..].chars().next();
+    let is_ambiguous = matches!(next, Some(c) if c == '=' || c.is_ascii_alphanumeric());
+
+    if in_attribute && is_ambiguous {
+        None
+    } else {
+        Some((value, len))
+    }
+}
+
+fn lookup_named(name: &str) -> Option<&'static str> {
+    NAMED_CHARACTER_REFERENCES
+        .binary_search_by_key(&name, |(n, _)| n)
+        .ok()
+        .map(|i| NAMED_CHARACTER_REFERENCES[i].1)
+}
+
+/// Decode a numeric reference following `&#`
+///
+/// Returns the character and the length consumed.
+///
+/// Spec: https://html.spec.whatwg.org/multipage/parsing.html#numeric-character-reference-end-state
+///
+fn decode_numeric(input: &str) -> Option<(char, usize)> {
+    let (radix, prefix_len) = match input.chars().next() {
+        Some('x') | Some('X') => (16, 1),
+        _ => (10, 0),
+    };
+    let digits = &input[prefix_len..];
+    let digits_len = digits
+        .find(|c: char| !c.is_digit(radix))
+        .unwrap_or(digits.len());
+    if digits_len == 0 {
+        return None;
+    }
+
+    let semicolon_len = if digits[digits_len..].starts_with(';') {
+        1
+    } else {
+        0
+    };
+    let code = u32::from_str_radix(&digits[..digits_len], radix).unwrap_or(u32::MAX);
+    let c = match code {
+        0x80..=0x9f => windows_1252(code),
+        0 => '\u{fffd}',
+        _ => std::char::from_u32(code).unwrap_or('\u{fffd}'),
+    };
+
+    Some((c, prefix_len + digits_len + semicolon_len))
+}
+
+/// Replacements for C1 control references, per Windows-1252
+fn windows_1252(code: u32) -> char {
+    const TABLE: [char; 32] = [
+        '\u{20ac}', '\u{81}', '\u{201a}', '\u{192}', '\u{201e}', '\u{2026}', '\u{2020}',
+        '\u{2021}', '\u{2c6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8d}', '\u{17d}',
+        '\u{8f}', '\u{90}', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}',
+        '\u{2014}', '\u{2dc}', '\u{2122}', '\u{161}', '\u{203a}', '\u{153}', '\u{9d}', '\u{17e}',
+        '\u{178}',
+    ];
+
+    TABLE[(code - 0x80) as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_without_references() {
+        assert!(matches!(
+            decode_text("Plain text"),
+            Cow::Borrowed("Plain text")
+        ));
+    }
+
+    #[test]
+    fn decode_named() {
+        assert_eq!(decode_text("Fish &amp; chips &lt;3"), "Fish & chips <3");
+    }
+
+    #[test]
+    fn decode_named_multiple_chars() {
+        assert_eq!(decode_text("&NotEqualTilde;"), "\u{2242}\u{338}");
+    }
+
+    #[test]
+    fn decode_numeric() {
+        assert_eq!(decode_text("It&#39;s &#x27;quoted&#X27;"), "It's 'quoted'");
+    }
+
+    #[test]
+    fn decode_numeric_replacements() {
+        assert_eq!(
+            decode_text("&#0;&#x110000;&#xD800;&#128;"),
+            "\u{fffd}\u{fffd}\u{fffd}\u{20ac}"
+        );
+    }
+
+    #[test]
+    fn decode_legacy_without_semicolon() {
+        assert_eq!(decode_text("&copy 2020 &notit;"), "\u{a9} 2020 \u{ac}it;");
+    }
+
+    #[test]
+    fn decode_unknown_left_alone() {
+        assert_eq!(decode_text("AT&T &bogus; &# &"), "AT&T &bogus; &# &");
+    }
+
+    #[test]
+    fn decode_attribute_legacy_ambiguous() {
+        assert_eq!(
+            decode_attribute_value("?a=1&copy=2&amp;b=&lt;"),
+            "?a=1&copy=2&b=<"
+        );
+    }
+
+    #[test]
+    fn escape_text_markup() {
+        assert_eq!(
+            escape_text("a < b && c > d"),
+            "a &lt; b &amp;&amp; c &gt; d"
+        );
+    }
+
+    #[test]
+    fn escape_attribute_quotes() {
+        assert_eq!(
+            escape_attribute_value("say \"hi\" & <bye>"),
+            "say &quot;hi&quot; &amp; <bye>"
+        );
+    }
+
+    #[test]
+    fn escape_round_trip() {
+        let value = "say \"hi\" &amp; <bye>";
+        assert_eq!(
+            decode_attribute_value(&escape_attribute_value(value)),
+            value
+        );
+    }
+}
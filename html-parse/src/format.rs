@@ -1,25 +1,147 @@
 use crate::depth::DepthChange;
+use crate::escape::escape_quotes;
+use crate::standard_elements::{BLOCK_HTML_ELEMENTS, WHITESPACE_PRESERVING_HTML_ELEMENTS};
 use crate::{HTMLPart, HTMLTag, HTMLTagKind};
 use std::borrow::Cow;
 
 const INDENT: &str = "  ";
 
+/// Layout for formatted HTML
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FormatOptions<'s> {
+    /// Reproduce the source for parts parsed from it
+    ///
+    /// Parts without a span, like embed results, are written out
+    /// without any added whitespace. Parts with a span are always
+    /// taken from the source, so a modified part needs its span
+    /// reset to the default for the change to show.
+    ///
+    Verbatim(&'s str),
+
+    /// Each element on its own line, indented by depth
+    ///
+    /// Text and inline elements stay on the line they're on, and
+    /// whitespace preserving elements like `pre` are left alone.
+    /// Whitespace between inline elements is kept as a single space.
+    ///
+    Pretty { indent: String },
+
+    /// No added whitespace, with runs of whitespace in text
+    /// collapsed to a single space
+    Minified,
+}
+
+impl Default for FormatOptions<'_> {
+    fn default() -> Self {
+        FormatOptions::Pretty {
+            indent: INDENT.into(),
+        }
+    }
+}
+
 pub fn format_html<'a, T>(html_parts: T) -> String
 where
     T: IntoIterator<Item = &'a HTMLPart<'a>>,
 {
-    let mut output = String::new();
-    let mut depth: isize = 0;
-    let mut inside_text: Option<isize> = None;
+    format_html_with(html_parts, FormatOptions::default())
+}
 
-    for (i, hp) in html_parts.into_iter().enumerate() {
-        let new_depth = if let HTMLPart::Tag(tag) = &hp {
-            (depth + tag.kind.depth_change()).max(0)
-        } else {
-            depth
+pub fn format_html_with<'a, T>(html_parts: T, options: FormatOptions) -> String
+where
+    T: IntoIterator<Item = &'a HTMLPart<'a>>,
+{
+    let mut formatter = HTMLFormatter::with_options(options);
+
+    html_parts
+        .into_iter()
+        .map(|hp| formatter.format_part(hp))
+        .collect()
+}
+
+/// Incremental HTML formatter
+///
+/// Formats parts one at a time, exactly like `format_html_with`,
+/// so output can be written out as parts are parsed.
+///
+#[derive(Debug, Default)]
+pub struct HTMLFormatter<'s> {
+    options: FormatOptions<'s>,
+    depth: isize,
+    inside_text: Option<isize>,
+    preserve_depth: Option<isize>,
+    is_started: bool,
+    is_after_text: bool,
+}
+
+impl<'s> HTMLFormatter<'s> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_options(options: FormatOptions<'s>) -> Self {
+        HTMLFormatter {
+            options,
+            ..Self::default()
+        }
+    }
+
+    /// Format the next part, including any preceding whitespace
+    pub fn format_part(&mut self, hp: &HTMLPart) -> String {
+        let depth = self.depth;
+        let new_depth = (depth + hp.depth_change()).max(0);
+        let is_preserved = self.preserve_depth.is_some();
+
+        let output = match &self.options {
+            FormatOptions::Verbatim(source) => {
+                let span = hp.span();
+                match source.get(span.start..span.end) {
+                    Some(original) if !span.is_empty() => original.to_owned(),
+                    _ => format_html_part(hp),
+                }
+            }
+            FormatOptions::Pretty { .. } if is_preserved => format_html_part(hp),
+            // Source whitespace after block boundaries gives way to
+            // indentation
+            FormatOptions::Pretty { .. } if is_whitespace_text(hp) && !self.is_after_text => {
+                return String::new()
+            }
+            FormatOptions::Pretty { indent } if is_whitespace_text(hp) => {
+                let indent = indent.clone();
+                let mut output = self.pretty_whitespace(hp, &indent, depth, new_depth);
+                output.push(' ');
+                output
+            }
+            FormatOptions::Pretty { indent } => {
+                let indent = indent.clone();
+                let mut output = self.pretty_whitespace(hp, &indent, depth, new_depth);
+                output.push_str(&format_html_part(hp));
+                output
+            }
+            FormatOptions::Minified => match hp {
+                HTMLPart::Text(text, _) if !is_preserved => collapse_whitespace(text),
+                _ => format_html_part(hp),
+            },
         };
 
-        match (inside_text, &hp) {
+        self.track_preserved(hp, depth, new_depth);
+        self.depth = new_depth;
+        self.is_started = true;
+        self.is_after_text = !is_block_boundary(Some(hp));
+
+        output
+    }
+
+    /// Newline and indentation to go before a part
+    fn pretty_whitespace(
+        &mut self,
+        hp: &HTMLPart,
+        indent: &str,
+        depth: isize,
+        new_depth: isize,
+    ) -> String {
+        let mut output = String::new();
+
+        match (self.inside_text, &hp) {
             (
                 Some(text_depth),
                 HTMLPart::Tag(HTMLTag {
@@ -27,10 +149,13 @@ where
                     ..
                 }),
             ) if text_depth == depth => {
-                inside_text = None;
+                self.inside_text = None;
             }
             (None, HTMLPart::Text(..)) => {
-                inside_text = Some(depth);
+                self.inside_text = Some(depth);
+            }
+            (None, HTMLPart::Tag(tag)) if tag.kind != HTMLTagKind::Close && is_inline(tag) => {
+                self.inside_text = Some(depth);
             }
             (
                 None,
@@ -39,33 +164,98 @@ where
                     ..
                 }),
             ) => {
-                if 0 < i {
+                if self.is_started {
                     output.push('\n');
                 }
-                output.push_str(&INDENT.repeat(new_depth as usize));
+                output.push_str(&indent.repeat(new_depth as usize));
             }
             (None, _) => {
-                if 0 < i {
+                if self.is_started {
                     output.push('\n');
                 }
-                output.push_str(&INDENT.repeat(depth as usize));
+                output.push_str(&indent.repeat(depth as usize));
             }
             _ => {}
         };
 
-        depth = new_depth;
-        output.push_str(&format_html_part(&hp));
+        output
     }
 
-    output
+    /// Keep track of whether parts are within a whitespace
+    /// preserving element
+    fn track_preserved(&mut self, hp: &HTMLPart, depth: isize, new_depth: isize) {
+        match (self.preserve_depth, hp) {
+            (None, HTMLPart::Tag(tag))
+                if tag.kind == HTMLTagKind::Open && is_whitespace_preserving(tag) =>
+            {
+                self.preserve_depth = Some(depth);
+            }
+            (Some(preserve_depth), HTMLPart::Tag(tag))
+                if tag.kind == HTMLTagKind::Close && new_depth <= preserve_depth =>
+            {
+                self.preserve_depth = None;
+            }
+            _ => {}
+        }
+    }
+}
+
+pub(crate) fn is_whitespace_text(hp: &HTMLPart) -> bool {
+    matches!(hp, HTMLPart::Text(text, _) if text.chars().all(|c| c.is_ascii_whitespace()))
+}
+
+pub(crate) fn is_inline(tag: &HTMLTag) -> bool {
+    !BLOCK_HTML_ELEMENTS.contains(tag.name.to_ascii_lowercase().as_str())
+}
+
+/// Whether a part borders text on a block level, making adjacent
+/// whitespace insignificant
+///
+/// `None` stands for the start or end of the input.
+///
+pub(crate) fn is_block_boundary(hp: Option<&HTMLPart>) -> bool {
+    match hp {
+        None => true,
+        Some(HTMLPart::Tag(tag)) => !is_inline(tag),
+        Some(HTMLPart::Text(..)) => false,
+        Some(_) => true,
+    }
+}
+
+fn is_whitespace_preserving(tag: &HTMLTag) -> bool {
+    WHITESPACE_PRESERVING_HTML_ELEMENTS.contains(tag.name.to_ascii_lowercase().as_str())
+}
+
+/// Collapse each run of whitespace into a single space
+pub(crate) fn collapse_whitespace(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut is_whitespace = false;
+
+    for c in text.chars() {
+        if c.is_ascii_whitespace() {
+            if !is_whitespace {
+                collapsed.push(' ');
+            }
+            is_whitespace = true;
+        } else {
+            collapsed.push(c);
+            is_whitespace = false;
+        }
+    }
+
+    collapsed
 }
 
-fn format_html_part(part: &HTMLPart) -> String {
+pub(crate) fn format_html_part(part: &HTMLPart) -> String {
     match part {
-        HTMLPart::Comment(comment) => format!("<!--{}-->", comment),
-        HTMLPart::DocType => "<!DOCTYPE html>".into(),
+        HTMLPart::Bogus(bogus, _) => bogus.to_string(),
+        HTMLPart::CData(text, _) => format!("<![CDATA[{}]]>", text),
+        HTMLPart::Comment(comment, _) => format!("<!--{}-->", comment),
+        // Verbatim output copies the doctype as written instead
+        HTMLPart::DocType(doctype, _) => format!("<!DOCTYPE {}>", doctype),
+        HTMLPart::ProcessingInstruction(instruction, _) => format!("<?{}?>", instruction),
         HTMLPart::Tag(tag) => format_html_tag(tag),
-        HTMLPart::Text(text) => match text {
+        HTMLPart::Text(text, _) => match text {
             Cow::Borrowed(str) => (*str).to_owned(),
             Cow::Owned(string) => string.clone(),
         },
@@ -97,28 +287,177 @@ fn format_html_tag(tag: &HTMLTag) -> String {
     )
 }
 
-fn format_html_attribute(attribute: &(&str, Option<&str>)) -> String {
+/// Format attribute
+///
+/// Values are kept as they are in the source, character references
+/// and all, so only double quotes from single quoted or unquoted
+/// values need escaping.
+///
+fn format_html_attribute(attribute: &(Cow<str>, Option<Cow<str>>)) -> String {
     let (name, maybe_value) = attribute;
 
     match maybe_value {
-        Some(value) => format!("{}=\"{}\"", name, value),
-        None => (*name).into(),
+        Some(value) => format!("{}=\"{}\"", name, escape_quotes(value)),
+        None => name.to_string(),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::Span;
+
+    #[test]
+    fn format_incrementally() {
+        let parts = crate::parse_html("<ul><li>One</li><li><br></li></ul>").unwrap();
+        let mut formatter = HTMLFormatter::new();
+        let formatted: String = parts.iter().map(|hp| formatter.format_part(hp)).collect();
+
+        assert_eq!(formatted, format_html(&parts));
+    }
+
+    #[test]
+    fn format_verbatim_round_trip() {
+        let input = "<!doctype html>\n<P CLASS=a>Hi  <b\tid='x'>there</b>\n<!-- c --><br></P>";
+        let parts = crate::parse_html(input).unwrap();
+
+        assert_eq!(
+            format_html_with(&parts, FormatOptions::Verbatim(input)),
+            input
+        );
+    }
+
+    #[test]
+    fn format_verbatim_doctypes() {
+        for input in &[
+            "<!doctype html>\n<p>a</p>",
+            "<!DocType   html >\n<p>a</p>",
+            "<!DOCTYPE\n  html SYSTEM \"about:legacy-compat\">\n<p>a</p>",
+        ] {
+            let parts = crate::parse_html(input).unwrap();
+
+            assert_eq!(
+                format_html_with(&parts, FormatOptions::Verbatim(input)),
+                *input
+            );
+        }
+    }
+
+    #[test]
+    fn format_verbatim_unparsed_parts() {
+        let input = "<p>Today is <run command=\"date\" /></p>";
+        let mut parts = crate::parse_html(input).unwrap();
+        parts[2] = HTMLPart::Text("Monday".into(), Span::default());
+
+        assert_eq!(
+            format_html_with(&parts, FormatOptions::Verbatim(input)),
+            "<p>Today is Monday</p>"
+        );
+    }
+
+    #[test]
+    fn format_pretty_indent() {
+        let parts = crate::parse_html("<ul><li>One</li></ul>").unwrap();
+        let options = FormatOptions::Pretty {
+            indent: "\t".into(),
+        };
+
+        assert_eq!(
+            format_html_with(&parts, options),
+            "<ul>\n\t<li>One</li>\n</ul>"
+        );
+    }
+
+    #[test]
+    fn format_pretty_preserves_pre() {
+        let parts = crate::parse_html(
+            "<div><pre><code>fn main() {\n    go();\n}</code>\n</pre><p>x</p></div>",
+        )
+        .unwrap();
+
+        assert_eq!(
+            format_html(&parts),
+            "<div>\n  <pre><code>fn main() {\n    go();\n}</code>\n</pre>\n  <p>x</p>\n</div>"
+        );
+    }
+
+    #[test]
+    fn format_pretty_inline() {
+        let parts =
+            crate::parse_html("<div><p><a href=\"/\">Home</a> page</p><img src=\"a.png\"></div>")
+                .unwrap();
+
+        assert_eq!(
+            format_html(&parts),
+            "<div>\n  <p><a href=\"/\">Home</a> page</p><img src=\"a.png\" /></div>"
+        );
+    }
+
+    #[test]
+    fn format_pretty_keeps_inline_spacing() {
+        let parts = crate::parse_html("<div>\n  <p><b>a</b>\n  <i>b</i></p>\n</div>").unwrap();
+
+        assert_eq!(
+            format_html(&parts),
+            "<div>\n  <p><b>a</b> <i>b</i></p>\n</div>"
+        );
+    }
+
+    #[test]
+    fn format_minified() {
+        let parts = crate::parse_html(
+            "<div>\n  <p>Some\n    text</p>\n  <pre>a\n  b</pre>\n  <script>// go\ngo()</script>\n</div>",
+        )
+        .unwrap();
+
+        assert_eq!(
+            format_html_with(&parts, FormatOptions::Minified),
+            "<div> <p>Some text</p> <pre>a\n  b</pre> <script>// go\ngo()</script> </div>"
+        );
+    }
 
     #[test]
     fn format_doctype() {
-        assert_eq!(format_html(&vec![HTMLPart::DocType]), "<!DOCTYPE html>");
+        assert_eq!(
+            format_html(&vec![HTMLPart::DocType("html".into(), Span::default())]),
+            "<!DOCTYPE html>"
+        );
+    }
+
+    #[test]
+    fn format_legacy_doctype() {
+        assert_eq!(
+            format_html(&vec![HTMLPart::DocType(
+                "html SYSTEM \"about:legacy-compat\"".into(),
+                Span::default()
+            )]),
+            "<!DOCTYPE html SYSTEM \"about:legacy-compat\">"
+        );
+    }
+
+    #[test]
+    fn format_cdata() {
+        assert_eq!(
+            format_html(&vec![HTMLPart::CData("a < b".into(), Span::default())]),
+            "<![CDATA[a < b]]>"
+        );
+    }
+
+    #[test]
+    fn format_processing_instruction() {
+        assert_eq!(
+            format_html(&vec![HTMLPart::ProcessingInstruction(
+                "xml version=\"1.0\"".into(),
+                Span::default()
+            )]),
+            "<?xml version=\"1.0\"?>"
+        );
     }
 
     #[test]
     fn format_comment() {
         assert_eq!(
-            format_html(&vec![HTMLPart::Comment("BORKEN")]),
+            format_html(&vec![HTMLPart::Comment("BORKEN".into(), Span::default())]),
             "<!--BORKEN-->"
         );
     }
@@ -127,7 +466,8 @@ mod tests {
     fn format_text() {
         assert_eq!(
             format_html(&vec![HTMLPart::Text(
-                "This is a paragraph, \nwhat of it.".into()
+                "This is a paragraph, \nwhat of it.".into(),
+                Span::default()
             )]),
             "This is a paragraph, \nwhat of it."
         );
@@ -138,8 +478,9 @@ mod tests {
         assert_eq!(
             format_html(&vec![HTMLPart::Tag(HTMLTag {
                 kind: HTMLTagKind::Open,
-                name: "span",
+                name: "span".into(),
                 attributes: vec![],
+                span: Span::default(),
             })]),
             "<span>"
         );
@@ -150,20 +491,35 @@ mod tests {
         assert_eq!(
             format_html(&vec![HTMLPart::Tag(HTMLTag {
                 kind: HTMLTagKind::Open,
-                name: "span",
-                attributes: vec![("class", Some("alert"))],
+                name: "span".into(),
+                attributes: vec![("class".into(), Some("alert".into()))],
+                span: Span::default(),
             })]),
             "<span class=\"alert\">"
         );
     }
 
+    #[test]
+    fn format_open_quoted_attributes_tag() {
+        assert_eq!(
+            format_html(&vec![HTMLPart::Tag(HTMLTag {
+                kind: HTMLTagKind::Open,
+                name: "span".into(),
+                attributes: vec![("title".into(), Some("Say \"hi\" &amp; bye".into()))],
+                span: Span::default(),
+            })]),
+            "<span title=\"Say &quot;hi&quot; &amp; bye\">"
+        );
+    }
+
     #[test]
     fn format_open_boolean_attributes_tag() {
         assert_eq!(
             format_html(&vec![HTMLPart::Tag(HTMLTag {
                 kind: HTMLTagKind::Open,
-                name: "button",
-                attributes: vec![("disabled", None)],
+                name: "button".into(),
+                attributes: vec![("disabled".into(), None)],
+                span: Span::default(),
             })]),
             "<button disabled>"
         );
@@ -174,8 +530,9 @@ mod tests {
         assert_eq!(
             format_html(&vec![HTMLPart::Tag(HTMLTag {
                 kind: HTMLTagKind::Void,
-                name: "meta",
+                name: "meta".into(),
                 attributes: vec![],
+                span: Span::default(),
             })]),
             "<meta />"
         );
@@ -186,8 +543,9 @@ mod tests {
         assert_eq!(
             format_html(&vec![HTMLPart::Tag(HTMLTag {
                 kind: HTMLTagKind::Void,
-                name: "meta",
-                attributes: vec![("charset", Some("utf-8"))],
+                name: "meta".into(),
+                attributes: vec![("charset".into(), Some("utf-8".into()))],
+                span: Span::default(),
             })]),
             "<meta charset=\"utf-8\" />"
         );
@@ -198,8 +556,9 @@ mod tests {
         assert_eq!(
             format_html(&vec![HTMLPart::Tag(HTMLTag {
                 kind: HTMLTagKind::Void,
-                name: "button",
-                attributes: vec![("disabled", None)],
+                name: "button".into(),
+                attributes: vec![("disabled".into(), None)],
+                span: Span::default(),
             })]),
             "<button disabled />"
         );
@@ -210,8 +569,9 @@ mod tests {
         assert_eq!(
             format_html(&vec![HTMLPart::Tag(HTMLTag {
                 kind: HTMLTagKind::Close,
-                name: "div",
+                name: "div".into(),
                 attributes: vec![],
+                span: Span::default(),
             })]),
             "</div>"
         );
@@ -221,92 +581,111 @@ mod tests {
     fn format_html_doc() {
         assert_eq!(
             format_html(&vec![
-                HTMLPart::DocType,
+                HTMLPart::DocType("html".into(), Span::default()),
                 HTMLPart::Tag(HTMLTag {
                     kind: HTMLTagKind::Open,
-                    name: "html",
+                    name: "html".into(),
                     attributes: vec![],
+                    span: Span::default(),
                 }),
                 HTMLPart::Tag(HTMLTag {
                     kind: HTMLTagKind::Open,
-                    name: "header",
+                    name: "header".into(),
                     attributes: vec![],
+                    span: Span::default(),
                 }),
                 HTMLPart::Tag(HTMLTag {
                     kind: HTMLTagKind::Open,
-                    name: "title",
+                    name: "title".into(),
                     attributes: vec![],
+                    span: Span::default(),
                 }),
-                HTMLPart::Text("Title".into()),
+                HTMLPart::Text("Title".into(), Span::default()),
                 HTMLPart::Tag(HTMLTag {
                     kind: HTMLTagKind::Close,
-                    name: "title",
+                    name: "title".into(),
                     attributes: vec![],
+                    span: Span::default(),
                 }),
                 HTMLPart::Tag(HTMLTag {
                     kind: HTMLTagKind::Void,
-                    name: "link",
-                    attributes: vec![("rel", Some("stylesheet")), ("href", Some("./styles.css"))],
+                    name: "link".into(),
+                    attributes: vec![
+                        ("rel".into(), Some("stylesheet".into())),
+                        ("href".into(), Some("./styles.css".into()))
+                    ],
+                    span: Span::default(),
                 }),
                 HTMLPart::Tag(HTMLTag {
                     kind: HTMLTagKind::Close,
-                    name: "header",
+                    name: "header".into(),
                     attributes: vec![],
+                    span: Span::default(),
                 }),
                 HTMLPart::Tag(HTMLTag {
                     kind: HTMLTagKind::Open,
-                    name: "body",
+                    name: "body".into(),
                     attributes: vec![],
+                    span: Span::default(),
                 }),
                 HTMLPart::Tag(HTMLTag {
                     kind: HTMLTagKind::Open,
-                    name: "h1",
+                    name: "h1".into(),
                     attributes: vec![],
+                    span: Span::default(),
                 }),
-                HTMLPart::Text("Header".into()),
+                HTMLPart::Text("Header".into(), Span::default()),
                 HTMLPart::Tag(HTMLTag {
                     kind: HTMLTagKind::Close,
-                    name: "h1",
+                    name: "h1".into(),
                     attributes: vec![],
+                    span: Span::default(),
                 }),
                 HTMLPart::Tag(HTMLTag {
                     kind: HTMLTagKind::Open,
-                    name: "p",
+                    name: "p".into(),
                     attributes: vec![],
+                    span: Span::default(),
                 }),
-                HTMLPart::Text("Two lines".into()),
+                HTMLPart::Text("Two lines".into(), Span::default()),
                 HTMLPart::Tag(HTMLTag {
                     kind: HTMLTagKind::Void,
-                    name: "br",
+                    name: "br".into(),
                     attributes: vec![],
+                    span: Span::default(),
                 }),
-                HTMLPart::Text("of ".into()),
+                HTMLPart::Text("of ".into(), Span::default()),
                 HTMLPart::Tag(HTMLTag {
                     kind: HTMLTagKind::Open,
-                    name: "em",
+                    name: "em".into(),
                     attributes: vec![],
+                    span: Span::default(),
                 }),
-                HTMLPart::Text("text".into()),
+                HTMLPart::Text("text".into(), Span::default()),
                 HTMLPart::Tag(HTMLTag {
                     kind: HTMLTagKind::Close,
-                    name: "em",
+                    name: "em".into(),
                     attributes: vec![],
+                    span: Span::default(),
                 }),
-                HTMLPart::Text(".".into()),
+                HTMLPart::Text(".".into(), Span::default()),
                 HTMLPart::Tag(HTMLTag {
                     kind: HTMLTagKind::Close,
-                    name: "p",
+                    name: "p".into(),
                     attributes: vec![],
+                    span: Span::default(),
                 }),
                 HTMLPart::Tag(HTMLTag {
                     kind: HTMLTagKind::Close,
-                    name: "body",
+                    name: "body".into(),
                     attributes: vec![],
+                    span: Span::default(),
                 }),
                 HTMLPart::Tag(HTMLTag {
                     kind: HTMLTagKind::Close,
-                    name: "html",
+                    name: "html".into(),
                     attributes: vec![],
+                    span: Span::default(),
                 }),
             ]),
             "\
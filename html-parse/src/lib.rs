@@ -1,15 +1,27 @@
 mod depth;
 mod embeds;
+mod entities;
+mod escape;
 mod format;
+mod minify;
 mod parse;
+mod select;
 mod standard_attributes;
 mod standard_elements;
+mod tree;
 mod types;
 
 pub use depth::DepthChange;
-pub use embeds::{parse_embeds, HTMLEmbed, HTMLPartOrEmbed};
-pub use format::format_html;
-pub use parse::{parse_all_parts, parse_html, parse_tag};
+pub use embeds::{iter_embeds, parse_embeds, Embeds, HTMLEmbed, HTMLPartOrEmbed};
+pub use escape::{decode_attribute_value, decode_text, escape_attribute_value, escape_text};
+pub use format::{format_html, format_html_with, FormatOptions, HTMLFormatter};
+pub use minify::minify_html;
+pub use parse::{
+    parse_all_parts, parse_html, parse_html_lenient, parse_tag, tokenize_reader, ParseHTMLError,
+    Tokenizer,
+};
+pub use select::{ParseSelectorError, Selector};
 pub use standard_attributes::URI_HTML_ATTRIBUTES;
 pub use standard_elements::STANDARD_HTML_ELEMENTS;
-pub use types::{HTMLPart, HTMLTag, HTMLTagKind};
+pub use tree::{Ancestors, Descendants, Document, Node, NodeId};
+pub use types::{HTMLPart, HTMLTag, HTMLTagKind, Span};
@@ -0,0 +1,424 @@
+use crate::escape::escape_quotes;
+use crate::format::{collapse_whitespace, format_html_part, is_block_boundary, is_whitespace_text};
+use crate::standard_elements::{VOID_HTML_ELEMENTS, WHITESPACE_PRESERVING_HTML_ELEMENTS};
+use crate::tree::closes_implicitly;
+use crate::{HTMLPart, HTMLTag, HTMLTagKind};
+use std::borrow::Cow;
+
+/// Minify HTML for production
+///
+/// Goes further than the minified layout of `format_html_with`:
+///
+/// - Whitespace between and at the edges of block elements is dropped
+/// - Comments are dropped, except conditional comments and license
+///   comments starting with `!`
+/// - Close tags which the spec allows to be omitted are dropped
+/// - Attribute values are unquoted where that's unambiguous
+/// - Inline stylesheets and scripts are conservatively trimmed
+///
+/// Spec: https://html.spec.whatwg.org/multipage/syntax.html#optional-tags
+///
+pub fn minify_html<'a, T>(html_parts: T) -> String
+where
+    T: IntoIterator<Item = &'a HTMLPart<'a>>,
+{
+    let parts: Vec<&HTMLPart> = html_parts
+        .into_iter()
+        .filter(|hp| !is_droppable_comment(hp))
+        .collect();
+    let mut output = String::new();
+    let mut preserving: Option<&HTMLTag> = None;
+
+    for (i, hp) in parts.iter().enumerate() {
+        match (hp, preserving) {
+            (HTMLPart::Text(text, _), Some(element)) => {
+                output.push_str(&minify_preserved_text(element, text));
+            }
+            (HTMLPart::Text(text, _), None) => {
+                output.push_str(&minify_text(text, &parts, i));
+            }
+            (HTMLPart::Tag(tag), Some(element)) => {
+                if tag.kind == HTMLTagKind::Close && tag.name.eq_ignore_ascii_case(&element.name) {
+                    preserving = None;
+                }
+                output.push_str(&minify_tag(tag));
+            }
+            (HTMLPart::Tag(tag), None) => {
+                if tag.kind == HTMLTagKind::Close && is_omittable_close(tag, &parts[i + 1..]) {
+                    continue;
+                }
+                if tag.kind == HTMLTagKind::Open && is_whitespace_preserving(tag) {
+                    preserving = Some(tag);
+                }
+                output.push_str(&minify_tag(tag));
+            }
+            _ => output.push_str(&format_html_part(hp)),
+        }
+    }
+
+    output
+}
+
+/// Whether a comment can go
+///
+/// Conditional comments like `<!--[if IE]>` change what older
+/// browsers render, and `<!--! ... -->` marks a license.
+///
+fn is_droppable_comment(hp: &HTMLPart) -> bool {
+    match hp {
+        HTMLPart::Comment(comment, _) => {
+            let comment = comment.trim();
+            !(comment.starts_with('!')
+                || comment.starts_with("[if")
+                || comment.ends_with("[endif]"))
+        }
+        _ => false,
+    }
+}
+
+fn minify_text<'a>(text: &str, parts: &[&HTMLPart<'a>], i: usize) -> String {
+    let collapsed = collapse_whitespace(text);
+    let mut minified = collapsed.as_str();
+
+    if is_block_boundary(i.checked_sub(1).and_then(|i| parts.get(i)).copied()) {
+        minified = minified.trim_start_matches(' ');
+    }
+    if is_block_boundary(parts.get(i + 1).copied()) {
+        minified = minified.trim_end_matches(' ');
+    }
+
+    minified.to_owned()
+}
+
+/// Whether a close tag can be left out
+///
+/// Looks at what comes next, ignoring whitespace, which is dropped
+/// after block elements anyway.
+///
+fn is_omittable_close(tag: &HTMLTag, following: &[&HTMLPart]) -> bool {
+    let name = tag.name.to_ascii_lowercase();
+    let next = following.iter().find(|hp| !is_whitespace_text(hp));
+
+    match next {
+        Some(HTMLPart::Tag(next)) if next.kind == HTMLTagKind::Close => {
+            is_omittable_at_parent_end(&name, &next.name.to_ascii_lowercase())
+        }
+        Some(HTMLPart::Tag(next)) => name == "head" || closes_implicitly(&name, &next.name),
+        None => matches!(name.as_str(), "body" | "html"),
+        Some(_) => false,
+    }
+}
+
+/// Whether a close tag can be left out when its parent ends
+///
+/// A `p` can't be left open at the end of a custom element, which
+/// isn't an HTML element as far as the spec's exception goes.
+///
+fn is_omittable_at_parent_end(name: &str, parent: &str) -> bool {
+    match name {
+        "p" => {
+            !parent.contains('-')
+                && !matches!(
+                    parent,
+                    "a" | "audio" | "del" | "ins" | "map" | "noscript" | "video"
+                )
+        }
+        "body" | "dd" | "li" | "optgroup" | "option" | "rp" | "rt" | "tbody" | "td" | "tfoot"
+        | "th" | "tr" => true,
+        _ => false,
+    }
+}
+
+fn minify_tag(tag: &HTMLTag) -> String {
+    if tag.kind == HTMLTagKind::Close {
+        return format!("</{}>", tag.name);
+    }
+
+    let mut minified = format!("<{}", tag.name);
+    let mut is_last_unquoted = false;
+    for attribute in &tag.attributes {
+        let (formatted, is_unquoted) = minify_attribute(attribute);
+        minified.push(' ');
+        minified.push_str(&formatted);
+        is_last_unquoted = is_unquoted;
+    }
+
+    // Self-closing slashes only mean something in foreign content,
+    // like SVG, and mustn't run into an unquoted value
+    let is_void_element = VOID_HTML_ELEMENTS.contains(tag.name.to_ascii_lowercase().as_str());
+    if tag.kind == HTMLTagKind::Void && !is_void_element {
+        minified.push_str(if is_last_unquoted { " /" } else { "/" });
+    }
+    minified.push('>');
+
+    minified
+}
+
+/// Minify attribute
+///
+/// Returns whether the value was left unquoted too.
+///
+fn minify_attribute(attribute: &(Cow<str>, Option<Cow<str>>)) -> (String, bool) {
+    match attribute {
+        (name, Some(value)) if can_unquote(value) => (format!("{}={}", name, value), true),
+        (name, Some(value)) => (format!("{}=\"{}\"", name, escape_quotes(value)), false),
+        (name, None) => (name.to_string(), false),
+    }
+}
+
+/// Whether a value can go unquoted
+///
+/// Spec: https://html.spec.whatwg.org/multipage/syntax.html#unquoted
+///
+fn can_unquote(value: &str) -> bool {
+    !value.is_empty()
+        && !value
+            .chars()
+            .any(|c| c.is_ascii_whitespace() || matches!(c, '"' | '\'' | '=' | '<' | '>' | '`'))
+}
+
+fn is_whitespace_preserving(tag: &HTMLTag) -> bool {
+    WHITESPACE_PRESERVING_HTML_ELEMENTS.contains(tag.name.to_ascii_lowercase().as_str())
+}
+
+fn minify_preserved_text(element: &HTMLTag, text: &str) -> String {
+    let name = element.name.to_ascii_lowercase();
+    match name.as_str() {
+        "style" => minify_css(text),
+        "script" if is_javascript(element) => minify_javascript(text),
+        _ => text.to_owned(),
+    }
+}
+
+/// Whether a script element holds JavaScript, rather than data or
+/// templates
+fn is_javascript(script: &HTMLTag) -> bool {
+    match script.attribute_value("type") {
+        None => true,
+        Some(kind) => {
+            let kind = kind.trim().to_ascii_lowercase();
+            kind.is_empty() || kind == "module" || kind.contains("javascript")
+        }
+    }
+}
+
+/// Trim indentation and blank lines from JavaScript
+///
+/// Left as is other than trimming the ends if any string might
+/// span lines, either as a template literal or with an escaped
+/// newline.
+///
+fn minify_javascript(js: &str) -> String {
+    let may_have_multiline_strings = js.contains('`') || js.lines().any(|l| l.ends_with('\\'));
+    if may_have_multiline_strings {
+        return js.trim().to_owned();
+    }
+
+    js.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+/// Drop comments and insignificant whitespace from CSS
+///
+/// Strings are left alone, as are license comments starting with
+/// `/*!`.
+///
+fn minify_css(css: &str) -> String {
+    // A space before `:` may be a descendant combinator, as in
+    // `a :hover`, so only spaces after it go
+    const PUNCTUATION_BEFORE: &[char] = &['{', '}', ';', ',', ':'];
+    const PUNCTUATION_AFTER: &[char] = &['{', '}', ';', ','];
+
+    let chars: Vec<char> = css.chars().collect();
+    let mut minified = String::with_capacity(css.len());
+    let mut quote: Option<char> = None;
+    let mut is_space_pending = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if let Some(q) = quote {
+            minified.push(c);
+            if c == '\\' {
+                if let Some(escaped) = chars.get(i + 1) {
+                    minified.push(*escaped);
+                    i += 1;
+                }
+            } else if c == q {
+                quote = None;
+            }
+            i += 1;
+            continue;
+        }
+
+        if c == '/' && chars.get(i + 1) == Some(&'*') {
+            let end = (i + 2..chars.len())
+                .find(|j| chars[*j] == '*' && chars.get(j + 1) == Some(&'/'))
+                .map_or(chars.len(), |j| j + 2);
+            if chars.get(i + 2) == Some(&'!') {
+                minified.extend(&chars[i..end]);
+            } else {
+                is_space_pending = true;
+            }
+            i = end;
+            continue;
+        }
+
+        if c.is_whitespace() {
+            is_space_pending = true;
+            i += 1;
+            continue;
+        }
+
+        let follows_punctuation = minified
+            .chars()
+            .last()
+            .is_none_or(|p| PUNCTUATION_BEFORE.contains(&p));
+        if is_space_pending && !follows_punctuation && !PUNCTUATION_AFTER.contains(&c) {
+            minified.push(' ');
+        }
+        is_space_pending = false;
+
+        match c {
+            '"' | '\'' => quote = Some(c),
+            '}' if minified.ends_with(';') => {
+                minified.pop();
+            }
+            _ => {}
+        }
+        minified.push(c);
+        i += 1;
+    }
+
+    minified
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_html;
+
+    fn minify(input: &str) -> String {
+        minify_html(&parse_html(input).unwrap())
+    }
+
+    #[test]
+    fn minify_whitespace() {
+        assert_eq!(
+            minify("<div>\n  <p>\n    Some   <em>emphasised</em>\n    text\n  </p>\n</div>"),
+            "<div><p>Some <em>emphasised</em> text</div>"
+        );
+    }
+
+    #[test]
+    fn minify_keeps_inline_spacing() {
+        assert_eq!(
+            minify("<span>a</span> <span>b</span>"),
+            "<span>a</span> <span>b</span>"
+        );
+    }
+
+    #[test]
+    fn minify_keeps_spacing_around_unknown_elements() {
+        assert_eq!(
+            minify(
+                "<p>Click <svg><path d=\"M0\" /></svg> here, <my-name></my-name> there \
+                 <script>go()</script> now</p>"
+            ),
+            "<p>Click <svg><path d=M0 /></svg> here, <my-name></my-name> there \
+             <script>go()</script> now</p>"
+        );
+    }
+
+    #[test]
+    fn minify_keeps_p_close_in_custom_element() {
+        assert_eq!(
+            minify("<my-card><p>Hi</p></my-card>"),
+            "<my-card><p>Hi</p></my-card>"
+        );
+    }
+
+    #[test]
+    fn minify_comments() {
+        assert_eq!(
+            minify("<!-- note --><!--! MIT License --><!--[if IE]><p>Old</p><![endif]-->"),
+            "<!--! MIT License --><!--[if IE]><p>Old</p><![endif]-->"
+        );
+    }
+
+    #[test]
+    fn minify_optional_close_tags() {
+        assert_eq!(
+            minify("<ul>\n  <li>One</li>\n  <li>Two</li>\n</ul><p>A</p><p>B</p><a><p>C</p></a>"),
+            "<ul><li>One<li>Two</ul><p>A<p>B</p><a><p>C</p></a>"
+        );
+    }
+
+    #[test]
+    fn minify_optional_document_tags() {
+        assert_eq!(
+            minify("<html><head><title>T</title></head>\n<body><p>Hi</p></body>\n</html>"),
+            "<html><head><title>T</title><body><p>Hi"
+        );
+    }
+
+    #[test]
+    fn minify_attribute_quotes() {
+        assert_eq!(
+            minify("<a href=\"/about\" title=\"About us\" class=\"\" data-x=\"a=b\" hidden>"),
+            "<a href=/about title=\"About us\" class=\"\" data-x=\"a=b\" hidden>"
+        );
+    }
+
+    #[test]
+    fn minify_void_slashes() {
+        assert_eq!(
+            minify("<br /><svg><circle r=\"5\" /><path d=\"M 0\" /></svg>"),
+            "<br><svg><circle r=5 /><path d=\"M 0\"/></svg>"
+        );
+    }
+
+    #[test]
+    fn minify_preserves_pre() {
+        assert_eq!(
+            minify("<pre>\n  a  <b>b</b>\n</pre>"),
+            "<pre>\n  a  <b>b</b>\n</pre>"
+        );
+    }
+
+    #[test]
+    fn minify_style() {
+        assert_eq!(
+            minify("<style>\n  /* Links */\n  a:hover ,\n  a b > c :first-child {\n    content: \"a  ;  b\";\n    margin: 0  auto;\n  }\n  /*! Kept */\n</style>"),
+            "<style>a:hover,a b > c :first-child{content:\"a  ;  b\";margin:0 auto}/*! Kept */</style>"
+        );
+    }
+
+    #[test]
+    fn minify_script() {
+        assert_eq!(
+            minify("<script>\n  if (a) {\n    go();\n  }\n\n</script>"),
+            "<script>if (a) {\ngo();\n}</script>"
+        );
+    }
+
+    #[test]
+    fn minify_script_template_literal() {
+        assert_eq!(
+            minify("<script>\n  let s = `a\n    b`;\n</script>"),
+            "<script>let s = `a\n    b`;</script>"
+        );
+    }
+
+    #[test]
+    fn minify_script_data() {
+        assert_eq!(
+            minify("<script type=\"application/ld+json\">\n  {\"a\": 1}\n</script>"),
+            "<script type=application/ld+json>\n  {\"a\": 1}\n</script>"
+        );
+    }
+}
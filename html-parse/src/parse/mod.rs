@@ -1,8 +1,15 @@
 mod parse_attribute;
+mod parse_cdata;
 mod parse_comment;
 mod parse_doctype;
+mod parse_error;
 mod parse_part;
+mod parse_processing_instruction;
+mod parse_raw_text;
 mod parse_tag;
+mod tokenizer;
 
-pub use parse_part::{parse_all_parts, parse_html};
+pub use parse_error::ParseHTMLError;
+pub use parse_part::{parse_all_parts, parse_html, parse_html_lenient};
 pub use parse_tag::parse_tag;
+pub use tokenizer::{tokenize_reader, Tokenizer};
@@ -1,17 +1,34 @@
 use nom::{
     branch::alt,
-    bytes::complete::is_not,
-    character::complete::{alpha1, char, one_of, space1},
+    bytes::complete::{is_not, take_till, take_while, take_while1},
+    character::complete::{char, one_of},
     sequence::tuple,
     IResult,
 };
 
+/// Whitespace within a tag, which includes form feeds
+///
+/// Spec: https://infra.spec.whatwg.org/#ascii-whitespace
+///
+fn is_html_space(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\u{0c}' | '\r')
+}
+
+/// Optional whitespace within a tag
+pub fn html_space0(input: &str) -> IResult<&str, &str> {
+    take_while(is_html_space)(input)
+}
+
+fn html_space1(input: &str) -> IResult<&str, &str> {
+    take_while1(is_html_space)(input)
+}
+
 /// Spaced attribute
 ///
 /// Spec: https://html.spec.whatwg.org/multipage/syntax.html#syntax-attributes
 ///
 pub fn spaced_attribute(input: &str) -> IResult<&str, (&str, Option<&str>)> {
-    let (input, matches) = tuple((space1, attribute_name, attribute_value))(input)?;
+    let (input, matches) = tuple((html_space1, attribute_name, attribute_value))(input)?;
     let (_, name, value) = matches;
 
     Ok((input, (name, value)))
@@ -19,10 +36,16 @@ pub fn spaced_attribute(input: &str) -> IResult<&str, (&str, Option<&str>)> {
 
 /// Attribute name
 ///
+/// Anything but controls, whitespace, quotes, `>`, `/` and `=`.
+/// Covers `data-*`, `aria-*`, namespaced names like `xlink:href`
+/// and framework shorthands like `@click` or `:prop`.
+///
 /// Spec: https://html.spec.whatwg.org/multipage/syntax.html#syntax-attribute-name
 ///
 fn attribute_name(input: &str) -> IResult<&str, &str> {
-    alpha1(input)
+    take_while1(|c: char| {
+        !c.is_control() && !c.is_whitespace() && !matches!(c, '"' | '\'' | '>' | '/' | '=')
+    })(input)
 }
 
 /// Attribute value
@@ -54,7 +77,8 @@ fn attribute_value(input: &str) -> IResult<&str, Option<&str>> {
 /// ```
 ///
 fn attribute_value_double_quoted(input: &str) -> IResult<&str, Option<&str>> {
-    let (input, matches) = tuple((char('='), char('"'), is_not("\""), char('"')))(input)?;
+    let (input, matches) =
+        tuple((char('='), char('"'), take_till(|c| c == '"'), char('"')))(input)?;
     let (_, _, value, _) = matches;
 
     Ok((input, Some(value)))
@@ -69,7 +93,8 @@ fn attribute_value_double_quoted(input: &str) -> IResult<&str, Option<&str>> {
 /// ```
 ///
 fn attribute_value_single_quoted(input: &str) -> IResult<&str, Option<&str>> {
-    let (input, matches) = tuple((char('='), char('\''), is_not("'"), char('\'')))(input)?;
+    let (input, matches) =
+        tuple((char('='), char('\''), take_till(|c| c == '\''), char('\'')))(input)?;
     let (_, _, value, _) = matches;
 
     Ok((input, Some(value)))
@@ -91,14 +116,17 @@ fn attribute_value_unquoted(input: &str) -> IResult<&str, Option<&str>> {
 
 /// Attribute value -- empty
 ///
+/// Followed by whitespace or the end of the tag, self-closing or not.
+///
 /// Example:
 ///
 /// ```html
 /// <div attribute>
+/// <input attribute/>
 /// ```
 ///
 fn attribute_value_empty(input: &str) -> IResult<&str, Option<&str>> {
-    let _ = one_of(" \t\r\n>")(input)?;
+    let _ = one_of(" \u{0c}\t\r\n/>")(input)?;
 
     Ok((input, None))
 }
@@ -107,6 +135,35 @@ fn attribute_value_empty(input: &str) -> IResult<&str, Option<&str>> {
 mod tests {
     use nom::{error::ErrorKind, Err};
 
+    #[test]
+    fn attribute_name_hyphenated() {
+        assert_eq!(
+            super::attribute_name("data-id=\"3\""),
+            Ok(("=\"3\"", "data-id"))
+        );
+    }
+
+    #[test]
+    fn attribute_name_namespaced() {
+        assert_eq!(
+            super::attribute_name("xlink:href=\"#a\""),
+            Ok(("=\"#a\"", "xlink:href"))
+        );
+    }
+
+    #[test]
+    fn attribute_name_shorthand() {
+        assert_eq!(super::attribute_name("@click=go"), Ok(("=go", "@click")));
+    }
+
+    #[test]
+    fn attribute_name_empty_error() {
+        assert_eq!(
+            super::attribute_name("=go"),
+            Err(Err::Error(("=go", ErrorKind::TakeWhile1)))
+        );
+    }
+
     #[test]
     fn attribute_value_double_quoted() {
         assert_eq!(
@@ -123,6 +180,12 @@ mod tests {
         );
     }
 
+    #[test]
+    fn attribute_value_quoted_empty() {
+        assert_eq!(super::attribute_value("=\"\">"), Ok((">", Some(""))));
+        assert_eq!(super::attribute_value("=''>"), Ok((">", Some(""))));
+    }
+
     #[test]
     fn attribute_value_unquoted() {
         assert_eq!(
@@ -136,6 +199,24 @@ mod tests {
         assert_eq!(super::attribute_value(" >"), Ok((" >", None)));
     }
 
+    #[test]
+    fn attribute_value_empty_self_closing() {
+        assert_eq!(super::attribute_value("/>"), Ok(("/>", None)));
+    }
+
+    #[test]
+    fn attribute_value_empty_form_feed() {
+        assert_eq!(super::attribute_value("\u{0c}>"), Ok(("\u{0c}>", None)));
+    }
+
+    #[test]
+    fn spaced_attribute_form_feed() {
+        assert_eq!(
+            super::spaced_attribute("\u{0c}disabled>"),
+            Ok((">", ("disabled", None)))
+        );
+    }
+
     #[test]
     fn attribute_value_incomplete() {
         assert_eq!(
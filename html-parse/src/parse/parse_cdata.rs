@@ -0,0 +1,55 @@
+use nom::{
+    bytes::complete::{tag, take_until},
+    sequence::tuple,
+    IResult,
+};
+
+use crate::types::{HTMLPart, Span};
+
+/// Parse CDATA section
+///
+/// Only meaningful within foreign content, like inline SVG, where
+/// its contents are text.
+///
+/// Spec: https://html.spec.whatwg.org/multipage/syntax.html#cdata-sections
+///
+pub fn parse_cdata_part(input: &str) -> IResult<&str, HTMLPart<'_>> {
+    let cdata_open = "<![CDATA[";
+    let cdata_close = "]]>";
+
+    let (input_rest, (_, text, _)) =
+        tuple((tag(cdata_open), take_until(cdata_close), tag(cdata_close)))(input)?;
+    let span = Span::new(0, input.len() - input_rest.len());
+
+    Ok((input_rest, HTMLPart::CData(text.into(), span)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_cdata_part;
+    use crate::{HTMLPart, Span};
+
+    #[test]
+    fn cdata_empty() {
+        assert_eq!(
+            parse_cdata_part("<![CDATA[]]>"),
+            Ok(("", HTMLPart::CData("".into(), Span::new(0, 12))))
+        );
+    }
+
+    #[test]
+    fn cdata_with_markup() {
+        assert_eq!(
+            parse_cdata_part("<![CDATA[a < b && <c>]]>text"),
+            Ok((
+                "text",
+                HTMLPart::CData("a < b && <c>".into(), Span::new(0, 24))
+            ))
+        );
+    }
+
+    #[test]
+    fn cdata_unclosed() {
+        assert!(parse_cdata_part("<![CDATA[a < b").is_err());
+    }
+}
@@ -4,7 +4,7 @@ use nom::{
     IResult,
 };
 
-use crate::types::HTMLPart;
+use crate::types::{HTMLPart, Span};
 
 /// Parse HTML comment
 ///
@@ -12,29 +12,30 @@ use crate::types::HTMLPart;
 ///
 /// Spec: https://html.spec.whatwg.org/multipage/syntax.html#comments
 ///
-pub fn parse_comment_part(input: &str) -> IResult<&str, HTMLPart> {
+pub fn parse_comment_part(input: &str) -> IResult<&str, HTMLPart<'_>> {
     let comment_open = "<!--";
     let comment_close = "-->";
 
-    let (input, (_, text, _)) = tuple((
+    let (input_rest, (_, text, _)) = tuple((
         tag(comment_open),
         take_until(comment_close),
         tag(comment_close),
     ))(input)?;
+    let span = Span::new(0, input.len() - input_rest.len());
 
-    Ok((input, HTMLPart::Comment(text)))
+    Ok((input_rest, HTMLPart::Comment(text.into(), span)))
 }
 
 #[cfg(test)]
 mod tests {
     use super::parse_comment_part;
-    use crate::HTMLPart;
+    use crate::{HTMLPart, Span};
 
     #[test]
     fn comment_empty() {
         assert_eq!(
             parse_comment_part("<!---->"),
-            Ok(("", HTMLPart::Comment("")))
+            Ok(("", HTMLPart::Comment("".into(), Span::new(0, 7))))
         );
     }
 
@@ -42,7 +43,13 @@ mod tests {
     fn comment_with_text() {
         assert_eq!(
             parse_comment_part("<!-- Here's a comment\n with a newline -->"),
-            Ok(("", HTMLPart::Comment(" Here's a comment\n with a newline ")))
+            Ok((
+                "",
+                HTMLPart::Comment(
+                    " Here's a comment\n with a newline ".into(),
+                    Span::new(0, 41)
+                )
+            ))
         );
     }
 
@@ -50,7 +57,7 @@ mod tests {
     fn comment_with_bad_contents() {
         assert_eq!(
             parse_comment_part("<!--><!--->"),
-            Ok(("", HTMLPart::Comment("><!-")))
+            Ok(("", HTMLPart::Comment("><!-".into(), Span::new(0, 11))))
         );
     }
 }
@@ -5,33 +5,38 @@ use nom::{
     IResult,
 };
 
-use crate::types::HTMLPart;
+use crate::types::{HTMLPart, Span};
 
 /// Parse HTML DOCTYPE
 ///
+/// Keeps its contents following the `DOCTYPE` keyword, like `html`,
+/// so legacy and XHTML doctypes survive formatting.
+///
 /// Spec: https://html.spec.whatwg.org/multipage/syntax.html#the-doctype
 ///
-pub fn parse_doctype_part(input: &str) -> IResult<&str, HTMLPart> {
-    let (input, _) = tuple((
+pub fn parse_doctype_part(input: &str) -> IResult<&str, HTMLPart<'_>> {
+    let (input_rest, (_, _, _, contents, _)) = tuple((
         tag("<!"),
         tag_no_case("doctype"),
         multispace1,
         is_not(">"),
         char('>'),
     ))(input)?;
-    Ok((input, HTMLPart::DocType))
+    let span = Span::new(0, input.len() - input_rest.len());
+
+    Ok((input_rest, HTMLPart::DocType(contents.into(), span)))
 }
 
 #[cfg(test)]
 mod tests {
     use super::parse_doctype_part;
-    use crate::HTMLPart;
+    use crate::{HTMLPart, Span};
 
     #[test]
     fn doctype_upper() {
         assert_eq!(
             parse_doctype_part("<!DOCTYPE html>"),
-            Ok(("", HTMLPart::DocType))
+            Ok(("", HTMLPart::DocType("html".into(), Span::new(0, 15))))
         );
     }
 
@@ -39,7 +44,7 @@ mod tests {
     fn doctype_lower() {
         assert_eq!(
             parse_doctype_part("<!doctype html>"),
-            Ok(("", HTMLPart::DocType))
+            Ok(("", HTMLPart::DocType("html".into(), Span::new(0, 15))))
         );
     }
 
@@ -47,7 +52,29 @@ mod tests {
     fn doctype_with_legacy() {
         assert_eq!(
             parse_doctype_part("<!DOCTYPE html SYSTEM \"about:legacy-compat\">"),
-            Ok(("", HTMLPart::DocType))
+            Ok((
+                "",
+                HTMLPart::DocType(
+                    "html SYSTEM \"about:legacy-compat\"".into(),
+                    Span::new(0, 44)
+                )
+            ))
+        );
+    }
+
+    #[test]
+    fn doctype_xhtml() {
+        let doctype = "<!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Strict//EN\"\n  \"http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd\">";
+
+        assert_eq!(
+            parse_doctype_part(doctype),
+            Ok((
+                "",
+                HTMLPart::DocType(
+                    doctype[10..doctype.len() - 1].into(),
+                    Span::new(0, doctype.len())
+                )
+            ))
         );
     }
 }
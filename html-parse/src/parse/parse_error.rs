@@ -0,0 +1,162 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::types::Span;
+
+/// HTML parsing error
+///
+/// Locates the offending input by line and column, keeping the
+/// source line around so it can be shown underlined.
+///
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseHTMLError {
+    pub file: Option<PathBuf>,
+    pub span: Span,
+    pub line: usize,
+    pub column: usize,
+    pub source_line: String,
+}
+
+impl ParseHTMLError {
+    /// Error for the input starting at a byte offset
+    ///
+    /// The offending input is taken to run until the end of its tag,
+    /// or the end of the line if no tag ends there.
+    ///
+    pub fn new(input: &str, offset: usize) -> Self {
+        let offset = offset.min(input.len());
+        let line_end = input[offset..]
+            .find('\n')
+            .map_or(input.len(), |i| offset + i);
+        let error_end = input[offset..line_end]
+            .find('>')
+            .map_or(line_end, |i| offset + i + 1);
+
+        Self::for_span(input, Span::new(offset, error_end))
+    }
+
+    /// Error for a known span of the input
+    pub fn for_span(input: &str, span: Span) -> Self {
+        let offset = span.start.min(input.len());
+        let line_start = input[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = input[offset..]
+            .find('\n')
+            .map_or(input.len(), |i| offset + i);
+        let source_line = input[line_start..line_end].trim_end_matches('\r');
+
+        ParseHTMLError {
+            file: None,
+            span,
+            line: input[..offset].matches('\n').count() + 1,
+            column: input[line_start..offset].chars().count() + 1,
+            source_line: source_line.to_owned(),
+        }
+    }
+
+    /// Attach the path of the file being parsed
+    pub fn with_file<P: AsRef<Path>>(self, file: P) -> Self {
+        ParseHTMLError {
+            file: Some(file.as_ref().to_owned()),
+            ..self
+        }
+    }
+
+    fn underline(&self) -> String {
+        let error_text = &self.source_line[self.prefix_len()..];
+        let error_len = self.span.len().min(error_text.len());
+        let underline_len = error_text[..error_len].chars().count().max(1);
+
+        format!(
+            "{}{}",
+            " ".repeat(self.column - 1),
+            "^".repeat(underline_len)
+        )
+    }
+
+    /// Byte length of the source line before the error
+    fn prefix_len(&self) -> usize {
+        self.source_line
+            .char_indices()
+            .nth(self.column - 1)
+            .map_or(self.source_line.len(), |(i, _)| i)
+    }
+}
+
+impl fmt::Display for ParseHTMLError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.file {
+            Some(file) => writeln!(
+                f,
+                "Can't parse HTML at {}:{}:{}",
+                file.display(),
+                self.line,
+                self.column
+            )?,
+            None => writeln!(
+                f,
+                "Can't parse HTML at line {}, column {}",
+                self.line, self.column
+            )?,
+        };
+
+        let line_number = self.line.to_string();
+        let gutter = " ".repeat(line_number.len());
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", line_number, self.source_line)?;
+        write!(f, "{} | {}", gutter, self.underline())
+    }
+}
+
+impl std::error::Error for ParseHTMLError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locate_first_line() {
+        let error = ParseHTMLError::new("<p>Hi</p><p class=>", 9);
+
+        assert_eq!(error.span, Span::new(9, 19));
+        assert_eq!((error.line, error.column), (1, 10));
+        assert_eq!(error.source_line, "<p>Hi</p><p class=>");
+    }
+
+    #[test]
+    fn locate_later_line() {
+        let error = ParseHTMLError::new("<div>\n  <p class=> text\n</div>", 8);
+
+        assert_eq!(error.span, Span::new(8, 18));
+        assert_eq!((error.line, error.column), (2, 3));
+        assert_eq!(error.source_line, "  <p class=> text");
+    }
+
+    #[test]
+    fn display_with_file() {
+        let error =
+            ParseHTMLError::new("<div>\n  <p class=> text\n</div>", 8).with_file("page.html");
+
+        assert_eq!(
+            error.to_string(),
+            "\
+Can't parse HTML at page.html:2:3
+  |
+2 |   <p class=> text
+  |   ^^^^^^^^^^"
+        );
+    }
+
+    #[test]
+    fn display_at_end_of_input() {
+        let error = ParseHTMLError::new("text <", 5);
+
+        assert_eq!(
+            error.to_string(),
+            "\
+Can't parse HTML at line 1, column 6
+  |
+1 | text <
+  |      ^"
+        );
+    }
+}
@@ -1,50 +1,90 @@
-use nom::{branch::alt, bytes::complete::is_not, combinator::all_consuming, multi::many0, IResult};
+use nom::{branch::alt, bytes::complete::is_not, combinator::all_consuming, Err, IResult};
 
+use super::parse_cdata::parse_cdata_part;
 use super::parse_comment::parse_comment_part;
 use super::parse_doctype::parse_doctype_part;
+use super::parse_error::ParseHTMLError;
+use super::parse_processing_instruction::parse_processing_instruction_part;
 use super::parse_tag::parse_tag;
-use crate::types::HTMLPart;
+use super::tokenizer::Tokenizer;
+use crate::types::{HTMLPart, Span};
 
-#[derive(Debug, PartialEq, Eq)]
-pub struct ParseHTMLError(String);
-
-impl std::fmt::Display for ParseHTMLError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let ParseHTMLError(text) = self;
-        write!(f, "{}", text)
+pub fn parse_html(input: &str) -> Result<Vec<HTMLPart<'_>>, ParseHTMLError> {
+    match parse_all_parts(input) {
+        Ok((_, parts)) => Ok(parts),
+        Err(Err::Error((rest, _))) | Err(Err::Failure((rest, _))) => {
+            Err(ParseHTMLError::new(input, input.len() - rest.len()))
+        }
+        Err(Err::Incomplete(_)) => Err(ParseHTMLError::new(input, input.len())),
     }
 }
 
-impl std::error::Error for ParseHTMLError {}
+/// Parse HTML without failing
+///
+/// Input which can't be parsed is kept verbatim as bogus parts,
+/// each with a warning locating it.
+///
+pub fn parse_html_lenient(input: &str) -> (Vec<HTMLPart<'_>>, Vec<ParseHTMLError>) {
+    let parts: Vec<HTMLPart> = Tokenizer::new(input).collect();
+    let warnings = parts
+        .iter()
+        .filter_map(|part| match part {
+            HTMLPart::Bogus(_, span) => Some(ParseHTMLError::for_span(input, *span)),
+            _ => None,
+        })
+        .collect();
+
+    (parts, warnings)
+}
 
-pub fn parse_html(input: &str) -> Result<Vec<HTMLPart>, ParseHTMLError> {
-    parse_all_parts(input)
-        .map(|(_, parts)| parts)
-        .map_err(|err| ParseHTMLError(format!("{}", err)))
+pub fn parse_all_parts(input: &str) -> IResult<&str, Vec<HTMLPart<'_>>> {
+    all_consuming(parse_parts)(input)
 }
 
-pub fn parse_all_parts(input: &str) -> IResult<&str, Vec<HTMLPart>> {
-    all_consuming(many0(parse_part))(input)
+/// Parse parts until none match
+///
+/// Like `many0(parse_part)`, except the contents of raw text
+/// elements are read as a single text part, regardless of any
+/// markup-like characters within.
+///
+/// Spans are relative to the start of the input.
+///
+fn parse_parts(input: &str) -> IResult<&str, Vec<HTMLPart<'_>>> {
+    let mut tokenizer = Tokenizer::new(input);
+    let mut parts = Vec::new();
+
+    while let Some(Ok(part)) = tokenizer.next_strict() {
+        parts.push(part);
+    }
+
+    Ok((tokenizer.rest(), parts))
 }
 
-fn parse_part(input: &str) -> IResult<&str, HTMLPart> {
+/// Parse a single part
+///
+/// Text comes first so whitespace before a tag is kept as text,
+/// rather than skipped over by `parse_tag`.
+///
+pub(super) fn parse_part(input: &str) -> IResult<&str, HTMLPart<'_>> {
     alt((
+        parse_text_part,
         parse_comment_part,
+        parse_cdata_part,
         parse_doctype_part,
+        parse_processing_instruction_part,
         parse_tag_part,
-        parse_text_part,
     ))(input)
 }
 
-fn parse_tag_part(input: &str) -> IResult<&str, HTMLPart> {
+fn parse_tag_part(input: &str) -> IResult<&str, HTMLPart<'_>> {
     let (input, tag) = parse_tag(input)?;
     Ok((input, HTMLPart::Tag(tag)))
 }
 
-fn parse_text_part(input: &str) -> IResult<&str, HTMLPart> {
+fn parse_text_part(input: &str) -> IResult<&str, HTMLPart<'_>> {
     let (input, text) = is_not("<")(input)?;
 
-    Ok((input, HTMLPart::Text(text.into())))
+    Ok((input, HTMLPart::Text(text.into(), Span::new(0, text.len()))))
 }
 
 #[cfg(test)]
@@ -56,7 +96,10 @@ mod tests {
     fn only_text() {
         assert_eq!(
             parse_html("No tags, just text."),
-            Ok(vec![HTMLPart::Text("No tags, just text.".into())]),
+            Ok(vec![HTMLPart::Text(
+                "No tags, just text.".into(),
+                Span::new(0, 19)
+            )]),
         );
     }
 
@@ -65,19 +108,24 @@ mod tests {
         assert_eq!(
             parse_html("Outside <p class=\"test\" toggle>Some content.</p> text"),
             Ok(vec![
-                HTMLPart::Text("Outside ".into()),
+                HTMLPart::Text("Outside ".into(), Span::new(0, 8)),
                 HTMLPart::Tag(HTMLTag {
                     kind: HTMLTagKind::Open,
-                    name: "p",
-                    attributes: vec![("class", Some("test")), ("toggle", None)],
+                    name: "p".into(),
+                    attributes: vec![
+                        ("class".into(), Some("test".into())),
+                        ("toggle".into(), None)
+                    ],
+                    span: Span::new(8, 31),
                 }),
-                HTMLPart::Text("Some content.".into()),
+                HTMLPart::Text("Some content.".into(), Span::new(31, 44)),
                 HTMLPart::Tag(HTMLTag {
                     kind: HTMLTagKind::Close,
-                    name: "p",
+                    name: "p".into(),
                     attributes: vec![],
+                    span: Span::new(44, 48),
                 }),
-                HTMLPart::Text(" text".into())
+                HTMLPart::Text(" text".into(), Span::new(48, 53))
             ]),
         );
     }
@@ -89,31 +137,180 @@ mod tests {
             Ok(vec![
                 HTMLPart::Tag(HTMLTag {
                     kind: HTMLTagKind::Open,
-                    name: "form",
+                    name: "form".into(),
                     attributes: vec![],
+                    span: Span::new(0, 6),
                 }),
                 HTMLPart::Tag(HTMLTag {
                     kind: HTMLTagKind::Open,
-                    name: "label",
+                    name: "label".into(),
                     attributes: vec![],
+                    span: Span::new(6, 13),
                 }),
-                HTMLPart::Text("Radio".into()),
+                HTMLPart::Text("Radio".into(), Span::new(13, 18)),
                 HTMLPart::Tag(HTMLTag {
                     kind: HTMLTagKind::Close,
-                    name: "label",
+                    name: "label".into(),
                     attributes: vec![],
+                    span: Span::new(18, 26),
                 }),
                 HTMLPart::Tag(HTMLTag {
                     kind: HTMLTagKind::Void,
-                    name: "input",
-                    attributes: vec![("type", Some("radio"))],
+                    name: "input".into(),
+                    attributes: vec![("type".into(), Some("radio".into()))],
+                    span: Span::new(26, 46),
+                }),
+                HTMLPart::Tag(HTMLTag {
+                    kind: HTMLTagKind::Close,
+                    name: "form".into(),
+                    attributes: vec![],
+                    span: Span::new(46, 53),
+                }),
+            ])
+        );
+    }
+
+    #[test]
+    fn script_raw_text() {
+        assert_eq!(
+            parse_html("<script>if (a < b && c > d) { go(); }</script>"),
+            Ok(vec![
+                HTMLPart::Tag(HTMLTag {
+                    kind: HTMLTagKind::Open,
+                    name: "script".into(),
+                    attributes: vec![],
+                    span: Span::new(0, 8),
                 }),
+                HTMLPart::Text("if (a < b && c > d) { go(); }".into(), Span::new(8, 37)),
                 HTMLPart::Tag(HTMLTag {
                     kind: HTMLTagKind::Close,
-                    name: "form",
+                    name: "script".into(),
                     attributes: vec![],
+                    span: Span::new(37, 46),
                 }),
             ])
         );
     }
+
+    #[test]
+    fn style_raw_text() {
+        assert_eq!(
+            parse_html("<style>ul > li { color: red }</style>"),
+            Ok(vec![
+                HTMLPart::Tag(HTMLTag {
+                    kind: HTMLTagKind::Open,
+                    name: "style".into(),
+                    attributes: vec![],
+                    span: Span::new(0, 7),
+                }),
+                HTMLPart::Text("ul > li { color: red }".into(), Span::new(7, 29)),
+                HTMLPart::Tag(HTMLTag {
+                    kind: HTMLTagKind::Close,
+                    name: "style".into(),
+                    attributes: vec![],
+                    span: Span::new(29, 37),
+                }),
+            ])
+        );
+    }
+
+    #[test]
+    fn textarea_escapable_raw_text() {
+        assert_eq!(
+            parse_html("<textarea><p>Not a tag &amp; still text</p></textarea>"),
+            Ok(vec![
+                HTMLPart::Tag(HTMLTag {
+                    kind: HTMLTagKind::Open,
+                    name: "textarea".into(),
+                    attributes: vec![],
+                    span: Span::new(0, 10),
+                }),
+                HTMLPart::Text(
+                    "<p>Not a tag &amp; still text</p>".into(),
+                    Span::new(10, 43)
+                ),
+                HTMLPart::Tag(HTMLTag {
+                    kind: HTMLTagKind::Close,
+                    name: "textarea".into(),
+                    attributes: vec![],
+                    span: Span::new(43, 54),
+                }),
+            ])
+        );
+    }
+
+    #[test]
+    fn empty_title() {
+        assert_eq!(
+            parse_html("<title></title>"),
+            Ok(vec![
+                HTMLPart::Tag(HTMLTag {
+                    kind: HTMLTagKind::Open,
+                    name: "title".into(),
+                    attributes: vec![],
+                    span: Span::new(0, 7),
+                }),
+                HTMLPart::Tag(HTMLTag {
+                    kind: HTMLTagKind::Close,
+                    name: "title".into(),
+                    attributes: vec![],
+                    span: Span::new(7, 15),
+                }),
+            ])
+        );
+    }
+
+    #[test]
+    fn valueless_attributes_before_tag_end() {
+        for input in &[
+            "<input disabled/>",
+            "<input type=\"x\" disabled/>",
+            "<input\tdisabled\u{0c}>",
+        ] {
+            assert_eq!(parse_html(input).map(|parts| parts.len()), Ok(1));
+        }
+    }
+
+    #[test]
+    fn error_location() {
+        let error = parse_html("<div>\n  <p class=>Text</p>\n</div>").unwrap_err();
+
+        assert_eq!(error.span, Span::new(8, 18));
+        assert_eq!((error.line, error.column), (2, 3));
+    }
+
+    #[test]
+    fn lenient_keeps_bogus_parts() {
+        let (parts, warnings) = parse_html_lenient("<p>1 <2</ ><br></p>");
+
+        assert_eq!(
+            &parts[2..5],
+            &[
+                HTMLPart::Bogus("<2".into(), Span::new(5, 7)),
+                HTMLPart::Bogus("</ >".into(), Span::new(7, 11)),
+                HTMLPart::Tag(HTMLTag {
+                    kind: HTMLTagKind::Void,
+                    name: "br".into(),
+                    attributes: vec![],
+                    span: Span::new(11, 15),
+                }),
+            ]
+        );
+        assert_eq!(
+            warnings
+                .iter()
+                .map(|warning| warning.column)
+                .collect::<Vec<usize>>(),
+            vec![6, 8]
+        );
+    }
+
+    #[test]
+    fn lenient_without_warnings() {
+        let input = "<p>Fine</p>";
+        let (parts, warnings) = parse_html_lenient(input);
+
+        assert_eq!(Ok(parts), parse_html(input));
+        assert!(warnings.is_empty());
+    }
 }
@@ -0,0 +1,63 @@
+use nom::{
+    bytes::complete::{tag, take_until},
+    sequence::tuple,
+    IResult,
+};
+
+use crate::types::{HTMLPart, Span};
+
+/// Parse processing instruction
+///
+/// Not part of HTML, which treats these as bogus comments, but
+/// they're kept for XML declarations like `<?xml version="1.0"?>`
+/// at the top of embedded documents.
+///
+/// Spec: https://www.w3.org/TR/xml/#sec-pi
+///
+pub fn parse_processing_instruction_part(input: &str) -> IResult<&str, HTMLPart<'_>> {
+    let instruction_open = "<?";
+    let instruction_close = "?>";
+
+    let (input_rest, (_, text, _)) = tuple((
+        tag(instruction_open),
+        take_until(instruction_close),
+        tag(instruction_close),
+    ))(input)?;
+    let span = Span::new(0, input.len() - input_rest.len());
+
+    Ok((
+        input_rest,
+        HTMLPart::ProcessingInstruction(text.into(), span),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_processing_instruction_part;
+    use crate::{HTMLPart, Span};
+
+    #[test]
+    fn xml_declaration() {
+        assert_eq!(
+            parse_processing_instruction_part("<?xml version=\"1.0\" encoding=\"UTF-8\"?>"),
+            Ok((
+                "",
+                HTMLPart::ProcessingInstruction(
+                    "xml version=\"1.0\" encoding=\"UTF-8\"".into(),
+                    Span::new(0, 38)
+                )
+            ))
+        );
+    }
+
+    #[test]
+    fn instruction_with_angle_bracket() {
+        assert_eq!(
+            parse_processing_instruction_part("<?php echo 1 > 0; ?><p>"),
+            Ok((
+                "<p>",
+                HTMLPart::ProcessingInstruction("php echo 1 > 0; ".into(), Span::new(0, 20))
+            ))
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use nom::IResult;
+
+use crate::standard_elements::{ESCAPABLE_RAW_TEXT_HTML_ELEMENTS, RAW_TEXT_HTML_ELEMENTS};
+
+/// Whether an element's contents are raw text
+///
+/// Covers both raw text and escapable raw text elements.
+///
+/// Spec: https://html.spec.whatwg.org/multipage/syntax.html#elements-2
+///
+pub fn is_raw_text_element(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    RAW_TEXT_HTML_ELEMENTS.contains(name.as_str())
+        || ESCAPABLE_RAW_TEXT_HTML_ELEMENTS.contains(name.as_str())
+}
+
+/// Parse raw text element contents
+///
+/// Consumes everything up until the element's close tag, which is
+/// matched case-insensitively and left in the input. Without a
+/// close tag, the rest of the input is consumed.
+///
+/// Never fails, though the text may be empty.
+///
+/// Spec: https://html.spec.whatwg.org/multipage/parsing.html#rawtext-state
+///
+pub fn parse_raw_text<'a>(name: &str, input: &'a str) -> IResult<&'a str, &'a str> {
+    let end = input
+        .match_indices("</")
+        .map(|(i, _)| i)
+        .find(|i| is_close_tag_start(name, &input[i + 2..]))
+        .unwrap_or(input.len());
+
+    Ok((&input[end..], &input[..end]))
+}
+
+/// Whether the input, following `</`, starts the close tag for `name`
+fn is_close_tag_start(name: &str, input: &str) -> bool {
+    match input.get(..name.len()) {
+        Some(candidate) if candidate.eq_ignore_ascii_case(name) => matches!(
+            input[name.len()..].chars().next(),
+            Some(' ')
+                | Some('\t')
+                | Some('\n')
+                | Some('\u{0c}')
+                | Some('\r')
+                | Some('/')
+                | Some('>')
+        ),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_text_until_close() {
+        assert_eq!(
+            parse_raw_text("script", "if (a < b) { go(); }</script>"),
+            Ok(("</script>", "if (a < b) { go(); }"))
+        );
+    }
+
+    #[test]
+    fn raw_text_ignores_other_close_tags() {
+        assert_eq!(
+            parse_raw_text("script", "document.write('</p></scripts>')</script>"),
+            Ok(("</script>", "document.write('</p></scripts>')"))
+        );
+    }
+
+    #[test]
+    fn raw_text_close_case_insensitive() {
+        assert_eq!(
+            parse_raw_text("style", "a > b { color: red }</STYLE >"),
+            Ok(("</STYLE >", "a > b { color: red }"))
+        );
+    }
+
+    #[test]
+    fn raw_text_empty() {
+        assert_eq!(parse_raw_text("title", "</title>"), Ok(("</title>", "")));
+    }
+
+    #[test]
+    fn raw_text_unclosed() {
+        assert_eq!(parse_raw_text("textarea", "a < b"), Ok(("", "a < b")));
+    }
+}
@@ -1,30 +1,46 @@
 use nom::{
     branch::alt,
-    character::complete::{alphanumeric1, char, multispace0, space1},
-    combinator::opt,
+    bytes::complete::take_till,
+    character::complete::{anychar, char, multispace0},
+    combinator::{opt, recognize, verify},
     multi::many0,
     sequence::tuple,
     IResult,
 };
 
-use super::parse_attribute::spaced_attribute;
+use super::parse_attribute::{html_space0, spaced_attribute};
 use crate::standard_elements::VOID_HTML_ELEMENTS;
-use crate::types::{HTMLTag, HTMLTagKind};
+use crate::types::{HTMLTag, HTMLTagKind, Span};
+use std::borrow::Cow;
 
-pub fn parse_tag(input: &str) -> IResult<&str, HTMLTag> {
-    let (input, _) = multispace0(input)?;
-    alt((attributes_tag, close_tag))(input)
+/// Parse HTML tag
+///
+/// Leading whitespace is skipped, and not included in the tag's span.
+///
+pub fn parse_tag(input: &str) -> IResult<&str, HTMLTag<'_>> {
+    let (tag_input, _) = multispace0(input)?;
+    let (input_rest, tag) = alt((attributes_tag, close_tag))(tag_input)?;
+    let whitespace_len = input.len() - tag_input.len();
+
+    Ok((
+        input_rest,
+        HTMLTag {
+            span: tag.span.offset(whitespace_len),
+            ..tag
+        },
+    ))
 }
 
-fn attributes_tag(input: &str) -> IResult<&str, HTMLTag> {
+fn attributes_tag(input: &str) -> IResult<&str, HTMLTag<'_>> {
     let (input_rest, matches) = tuple((
         char('<'),
         tag_name,
         many0(spaced_attribute),
+        html_space0,
         opt(void_delimiter),
         char('>'),
     ))(input)?;
-    let (_, name, attributes, void_delimiter, _) = matches;
+    let (_, name, attributes, _, void_delimiter, _) = matches;
 
     let has_void_name = VOID_HTML_ELEMENTS.contains(name);
     let has_void_delimiter = void_delimiter.is_some();
@@ -38,37 +54,49 @@ fn attributes_tag(input: &str) -> IResult<&str, HTMLTag> {
         input_rest,
         HTMLTag {
             kind,
-            name,
-            attributes,
+            name: name.into(),
+            attributes: attributes
+                .into_iter()
+                .map(|(name, value)| (name.into(), value.map(Cow::from)))
+                .collect(),
+            span: Span::new(0, input.len() - input_rest.len()),
         },
     ))
 }
 
-fn close_tag(input: &str) -> IResult<&str, HTMLTag> {
-    let (input, matches) = tuple((char('<'), char('/'), tag_name, char('>')))(input)?;
+fn close_tag(input: &str) -> IResult<&str, HTMLTag<'_>> {
+    let (input_rest, matches) = tuple((char('<'), char('/'), tag_name, char('>')))(input)?;
     let (_, _, name, _) = matches;
 
     Ok((
-        input,
+        input_rest,
         HTMLTag {
             kind: HTMLTagKind::Close,
-            name,
+            name: name.into(),
             attributes: Vec::new(),
+            span: Span::new(0, input.len() - input_rest.len()),
         },
     ))
 }
 
 /// Tag name
 ///
-/// Spec: https://html.spec.whatwg.org/multipage/syntax.html#syntax-tag-name
+/// An ASCII letter followed by anything up until whitespace, `/`
+/// or `>`. Covers custom elements like `<my-widget>` along with
+/// the standard alphanumeric names.
+///
+/// Spec: https://html.spec.whatwg.org/multipage/parsing.html#tag-name-state
 ///
 fn tag_name(input: &str) -> IResult<&str, &str> {
-    let (input, name) = alphanumeric1(input)?;
-    Ok((input, name))
+    recognize(tuple((
+        verify(anychar, |c: &char| c.is_ascii_alphabetic()),
+        take_till(|c| matches!(c, ' ' | '\t' | '\n' | '\u{0c}' | '\r' | '/' | '>')),
+    )))(input)
 }
 
+/// Self-closing `/`, after any whitespace ending the tag
 fn void_delimiter(input: &str) -> IResult<&str, ()> {
-    let (input, _) = tuple((space1, char('/')))(input)?;
+    let (input, _) = char('/')(input)?;
     Ok((input, ()))
 }
 
@@ -85,8 +113,9 @@ mod tests {
                 "",
                 HTMLTag {
                     kind: HTMLTagKind::Open,
-                    name: "div",
-                    attributes: Vec::new()
+                    name: "div".into(),
+                    attributes: Vec::new(),
+                    span: Span::new(0, 5),
                 }
             ))
         );
@@ -100,8 +129,25 @@ mod tests {
                 "",
                 HTMLTag {
                     kind: HTMLTagKind::Open,
-                    name: "h1",
-                    attributes: Vec::new()
+                    name: "h1".into(),
+                    attributes: Vec::new(),
+                    span: Span::new(0, 4),
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn open_after_whitespace() {
+        assert_eq!(
+            parse_tag("\n  <p>"),
+            Ok((
+                "",
+                HTMLTag {
+                    kind: HTMLTagKind::Open,
+                    name: "p".into(),
+                    attributes: Vec::new(),
+                    span: Span::new(3, 6),
                 }
             ))
         );
@@ -115,8 +161,9 @@ mod tests {
                 "",
                 HTMLTag {
                     kind: HTMLTagKind::Close,
-                    name: "div",
-                    attributes: Vec::new()
+                    name: "div".into(),
+                    attributes: Vec::new(),
+                    span: Span::new(0, 6),
                 }
             ))
         );
@@ -130,8 +177,89 @@ mod tests {
                 "",
                 HTMLTag {
                     kind: HTMLTagKind::Void,
-                    name: "input",
-                    attributes: Vec::new()
+                    name: "input".into(),
+                    attributes: Vec::new(),
+                    span: Span::new(0, 9),
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn void_xhtml_without_space() {
+        assert_eq!(
+            parse_tag("<br/>"),
+            Ok((
+                "",
+                HTMLTag {
+                    kind: HTMLTagKind::Void,
+                    name: "br".into(),
+                    attributes: Vec::new(),
+                    span: Span::new(0, 5),
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn void_namespaced_without_space() {
+        assert_eq!(
+            parse_tag("<svg:path d=\"M0\"/>"),
+            Ok((
+                "",
+                HTMLTag {
+                    kind: HTMLTagKind::Void,
+                    name: "svg:path".into(),
+                    attributes: vec![("d".into(), Some("M0".into()))],
+                    span: Span::new(0, 18),
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn void_valueless_attribute_without_space() {
+        assert_eq!(
+            parse_tag("<input type=\"x\" disabled/>"),
+            Ok((
+                "",
+                HTMLTag {
+                    kind: HTMLTagKind::Void,
+                    name: "input".into(),
+                    attributes: vec![("type".into(), Some("x".into())), ("disabled".into(), None)],
+                    span: Span::new(0, 26),
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn valueless_attribute_before_form_feed() {
+        assert_eq!(
+            parse_tag("<input\tdisabled\u{0c}>"),
+            Ok((
+                "",
+                HTMLTag {
+                    kind: HTMLTagKind::Void,
+                    name: "input".into(),
+                    attributes: vec![("disabled".into(), None)],
+                    span: Span::new(0, 17),
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn open_with_trailing_space() {
+        assert_eq!(
+            parse_tag("<div class=\"a\" >"),
+            Ok((
+                "",
+                HTMLTag {
+                    kind: HTMLTagKind::Open,
+                    name: "div".into(),
+                    attributes: vec![("class".into(), Some("a".into()))],
+                    span: Span::new(0, 16),
                 }
             ))
         );
@@ -145,8 +273,9 @@ mod tests {
                 "",
                 HTMLTag {
                     kind: HTMLTagKind::Void,
-                    name: "input",
-                    attributes: Vec::new()
+                    name: "input".into(),
+                    attributes: Vec::new(),
+                    span: Span::new(0, 7),
                 }
             ))
         );
@@ -160,8 +289,12 @@ mod tests {
                 "",
                 HTMLTag {
                     kind: HTMLTagKind::Open,
-                    name: "div",
-                    attributes: vec![("id", Some("main")), ("class", Some("layout"))]
+                    name: "div".into(),
+                    attributes: vec![
+                        ("id".into(), Some("main".into())),
+                        ("class".into(), Some("layout".into()))
+                    ],
+                    span: Span::new(0, 30),
                 }
             ))
         );
@@ -175,8 +308,12 @@ mod tests {
                 "",
                 HTMLTag {
                     kind: HTMLTagKind::Void,
-                    name: "input",
-                    attributes: vec![("type", Some("radio")), ("class", Some("custom-radio"))]
+                    name: "input".into(),
+                    attributes: vec![
+                        ("type".into(), Some("radio".into())),
+                        ("class".into(), Some("custom-radio".into()))
+                    ],
+                    span: Span::new(0, 43),
                 }
             ))
         );
@@ -197,4 +334,95 @@ mod tests {
             )))
         );
     }
+
+    #[test]
+    fn custom_element() {
+        assert_eq!(
+            parse_tag("<my-widget>"),
+            Ok((
+                "",
+                HTMLTag {
+                    kind: HTMLTagKind::Open,
+                    name: "my-widget".into(),
+                    attributes: Vec::new(),
+                    span: Span::new(0, 11),
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn custom_element_close() {
+        assert_eq!(
+            parse_tag("</my-widget>"),
+            Ok((
+                "",
+                HTMLTag {
+                    kind: HTMLTagKind::Close,
+                    name: "my-widget".into(),
+                    attributes: Vec::new(),
+                    span: Span::new(0, 12),
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn framework_attributes() {
+        assert_eq!(
+            parse_tag("<my-widget data-id=\"3\" aria-label=\"Hi\" @click=\"go\" v-on:foo=\"bar\" :prop=\"x\">"),
+            Ok((
+                "",
+                HTMLTag {
+                    kind: HTMLTagKind::Open,
+                    name: "my-widget".into(),
+                    attributes: vec![
+                        ("data-id".into(), Some("3".into())),
+                        ("aria-label".into(), Some("Hi".into())),
+                        ("@click".into(), Some("go".into())),
+                        ("v-on:foo".into(), Some("bar".into())),
+                        (":prop".into(), Some("x".into())),
+                    ],
+                    span: Span::new(0, 76),
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn namespaced_attributes() {
+        assert_eq!(
+            parse_tag("<use xlink:href=\"#icon\" />"),
+            Ok((
+                "",
+                HTMLTag {
+                    kind: HTMLTagKind::Void,
+                    name: "use".into(),
+                    attributes: vec![("xlink:href".into(), Some("#icon".into()))],
+                    span: Span::new(0, 26),
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn multiline_attributes() {
+        assert_eq!(
+            parse_tag("<a\n  href=\"/\"\n  hidden>"),
+            Ok((
+                "",
+                HTMLTag {
+                    kind: HTMLTagKind::Open,
+                    name: "a".into(),
+                    attributes: vec![("href".into(), Some("/".into())), ("hidden".into(), None)],
+                    span: Span::new(0, 23),
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn name_starting_with_digit_error() {
+        assert_eq!(parse_tag("<1a>"), Err(Err::Error(("1a>", ErrorKind::Char))));
+    }
 }
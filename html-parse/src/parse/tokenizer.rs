@@ -0,0 +1,372 @@
+use nom::Err;
+use std::io::{self, BufRead};
+use std::str;
+
+use super::parse_part::parse_part;
+use super::parse_raw_text::{is_raw_text_element, parse_raw_text};
+use crate::types::{HTMLPart, HTMLTagKind, Span};
+
+/// Lazy HTML tokenizer
+///
+/// Yields parts one at a time instead of parsing the whole input
+/// up front. Malformed markup doesn't stop it: a `<` which doesn't
+/// start a tag, comment or doctype is kept as a bogus part instead.
+///
+/// Spans are relative to the start of the input.
+///
+pub struct Tokenizer<'a> {
+    input: &'a str,
+    position: usize,
+    offset: usize,
+    raw_text_element: Option<String>,
+    is_partial: bool,
+}
+
+impl<'a> Tokenizer<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Tokenizer {
+            input,
+            position: 0,
+            offset: 0,
+            raw_text_element: None,
+            is_partial: false,
+        }
+    }
+
+    /// Input which hasn't been tokenized yet
+    pub fn rest(&self) -> &'a str {
+        &self.input[self.position..]
+    }
+
+    /// Next part, without recovering from malformed markup
+    ///
+    /// Returns the byte position of the offending input on error,
+    /// leaving it in place.
+    ///
+    pub(crate) fn next_strict(&mut self) -> Option<Result<HTMLPart<'a>, usize>> {
+        if let Some(name) = self.raw_text_element.take() {
+            let (remaining, text) = match parse_raw_text(&name, self.rest()) {
+                Ok(result) => result,
+                Err(_) => unreachable!("Raw text parsing never fails"),
+            };
+
+            // Without its close tag, raw text may continue in the
+            // next chunk
+            if self.is_partial && remaining.is_empty() {
+                self.raw_text_element = Some(name);
+                return None;
+            }
+            if !text.is_empty() {
+                let start = self.offset + self.position;
+                self.position += text.len();
+                let span = Span::new(start, start + text.len());
+                return Some(Ok(HTMLPart::Text(text.into(), span)));
+            }
+        }
+        if self.position == self.input.len() {
+            return None;
+        }
+
+        let rest = self.rest();
+        let (remaining, part) = match parse_part(rest) {
+            Ok(result) => result,
+            Err(Err::Incomplete(_)) | Err(Err::Error(_)) | Err(Err::Failure(_)) => {
+                return Some(Err(self.position))
+            }
+        };
+        let consumed = rest.len() - remaining.len();
+
+        // Text running to the end of partial input may continue
+        // in the next chunk
+        if self.is_partial && remaining.is_empty() && matches!(part, HTMLPart::Text(..)) {
+            return None;
+        }
+
+        if let HTMLPart::Tag(tag) = &part {
+            if tag.kind == HTMLTagKind::Open && is_raw_text_element(&tag.name) {
+                self.raw_text_element = Some(tag.name.to_string());
+            }
+        }
+
+        let part = part.offset_span(self.offset + self.position);
+        self.position += consumed;
+
+        Some(Ok(part))
+    }
+
+    /// Keep malformed input as a bogus part
+    ///
+    /// Takes the offending `<` up until the next `>`, or up until the
+    /// next `<` if that comes first.
+    ///
+    fn recover(&mut self) -> Option<HTMLPart<'a>> {
+        let rest = self.rest();
+        let len = rest[1..]
+            .find(['<', '>'])
+            .map_or(rest.len(), |i| match rest.as_bytes()[i + 1] {
+                b'>' => i + 2,
+                _ => i + 1,
+            });
+
+        // Markup at the end of partial input may just be cut off,
+        // and text may continue in the next chunk
+        if self.is_partial && (len == rest.len() || may_be_cut_off(rest)) {
+            return None;
+        }
+
+        let start = self.offset + self.position;
+        self.position += len;
+
+        Some(HTMLPart::Bogus(
+            rest[..len].into(),
+            Span::new(start, start + len),
+        ))
+    }
+}
+
+impl<'a> Iterator for Tokenizer<'a> {
+    type Item = HTMLPart<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_strict()? {
+            Ok(part) => Some(part),
+            Err(_) => self.recover(),
+        }
+    }
+}
+
+/// Whether markup failed to parse only for want of more input
+///
+/// Looks for the end of the comment, CDATA section or processing
+/// instruction, or else the first `>` outside of a quoted attribute
+/// value.
+///
+fn may_be_cut_off(input: &str) -> bool {
+    for (open, close) in &[("<!--", "-->"), ("<![CDATA[", "]]>"), ("<?", "?>")] {
+        if let Some(contents) = input.strip_prefix(open) {
+            return !contents.contains(close);
+        }
+        if open.starts_with(input) {
+            return true;
+        }
+    }
+
+    let mut quote = None;
+    let mut previous = ' ';
+    for c in input.chars() {
+        match (quote, c) {
+            (None, '"') | (None, '\'') if previous == '=' => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '>') => return false,
+            _ => {}
+        }
+        if !c.is_whitespace() {
+            previous = c;
+        }
+    }
+
+    true
+}
+
+/// Tokenize HTML from a reader
+///
+/// Reads the input in chunks, calling back with each part once it's
+/// complete, so the whole input is never held in memory at once.
+/// Parts only live as long as the call, with spans relative to the
+/// start of the input.
+///
+/// Input held back for an incomplete part is only tokenized again
+/// once a chunk could complete it, so long text takes linear time.
+///
+pub fn tokenize_reader<R, F>(mut reader: R, mut on_part: F) -> io::Result<()>
+where
+    R: BufRead,
+    F: FnMut(HTMLPart<'_>) -> io::Result<()>,
+{
+    let mut input = String::new();
+    let mut cut_off_bytes: Vec<u8> = Vec::new();
+    let mut scanned_len = 0;
+    let mut offset = 0;
+    let mut raw_text_element = None;
+
+    loop {
+        let chunk = reader.fill_buf()?;
+        let chunk_len = chunk.len();
+        let is_eof = chunk_len == 0;
+        cut_off_bytes.extend_from_slice(chunk);
+        reader.consume(chunk_len);
+
+        // Hold back a character cut off at the end of the chunk
+        let valid_len = match str::from_utf8(&cut_off_bytes) {
+            Ok(text) => text.len(),
+            Err(err) if err.error_len().is_none() && !is_eof => err.valid_up_to(),
+            Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidData, err)),
+        };
+        let valid = str::from_utf8(&cut_off_bytes[..valid_len])
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        input.push_str(valid);
+        cut_off_bytes.drain(..valid_len);
+
+        if !is_eof && !may_complete(&input, scanned_len, raw_text_element.as_deref()) {
+            scanned_len = input.len();
+            continue;
+        }
+
+        let mut tokenizer = Tokenizer {
+            input: &input,
+            position: 0,
+            offset,
+            raw_text_element,
+            is_partial: !is_eof,
+        };
+        for part in &mut tokenizer {
+            on_part(part)?;
+        }
+
+        let consumed = tokenizer.position;
+        raw_text_element = tokenizer.raw_text_element;
+        input.drain(..consumed);
+        scanned_len = input.len();
+        offset += consumed;
+
+        if is_eof {
+            return Ok(());
+        }
+    }
+}
+
+/// Whether input held back for an incomplete part may now complete
+/// it, going by what was added since it was last tokenized
+///
+/// Text ends at a `<`, and markup at a `>` or the next `<`. Raw text
+/// ends at its close tag, which may have started before.
+///
+fn may_complete(input: &str, scanned_len: usize, raw_text_element: Option<&str>) -> bool {
+    let bytes = input.as_bytes();
+    match raw_text_element {
+        Some(name) => bytes[scanned_len.saturating_sub(name.len() + 2)..]
+            .windows(2)
+            .any(|pair| pair == b"</"),
+        None if bytes.first() == Some(&b'<') => bytes[scanned_len.max(1)..]
+            .iter()
+            .any(|&byte| byte == b'<' || byte == b'>'),
+        None => bytes[scanned_len..].contains(&b'<'),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::HTMLTag;
+
+    #[test]
+    fn tokenize_lazily() {
+        let mut tokenizer = Tokenizer::new("<p>Hi</p> there");
+
+        assert!(matches!(
+            tokenizer.next(),
+            Some(HTMLPart::Tag(HTMLTag { name, .. })) if name == "p"
+        ));
+        assert_eq!(tokenizer.rest(), "Hi</p> there");
+    }
+
+    #[test]
+    fn tokenize_raw_text() {
+        let parts: Vec<HTMLPart> = Tokenizer::new("<script>a < b</script>").collect();
+
+        assert_eq!(parts[1], HTMLPart::Text("a < b".into(), Span::new(8, 13)));
+        assert_eq!(parts.len(), 3);
+    }
+
+    #[test]
+    fn recover_malformed_markup() {
+        let parts: Vec<HTMLPart> = Tokenizer::new("a <3 b<p class=>c<p>").collect();
+
+        assert_eq!(
+            parts,
+            vec![
+                HTMLPart::Text("a ".into(), Span::new(0, 2)),
+                HTMLPart::Bogus("<3 b".into(), Span::new(2, 6)),
+                HTMLPart::Bogus("<p class=>".into(), Span::new(6, 16)),
+                HTMLPart::Text("c".into(), Span::new(16, 17)),
+                HTMLPart::Tag(HTMLTag {
+                    kind: HTMLTagKind::Open,
+                    name: "p".into(),
+                    attributes: vec![],
+                    span: Span::new(17, 20),
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn recover_at_end() {
+        let parts: Vec<HTMLPart> = Tokenizer::new("text <").collect();
+
+        assert_eq!(parts[1], HTMLPart::Bogus("<".into(), Span::new(5, 6)));
+    }
+
+    /// Reader which returns one byte at a time
+    struct Trickle<'a>(&'a [u8]);
+
+    impl io::Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match (self.0.split_first(), buf.first_mut()) {
+                (Some((byte, rest)), Some(first)) => {
+                    *first = *byte;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn tokenize_reader_matches_str() {
+        let html = "<?xml version=\"1.0\"?><!DOCTYPE html><!-- a <b ✓ -->\
+                    <svg><style><![CDATA[a > b]]></style></svg><p class=\"a\">Café <3</p>\
+                    <a title='x > y'>link</a><script>if (a < b) {}</script><br/>done";
+        let expected: Vec<String> = Tokenizer::new(html)
+            .map(|part| format!("{:?}", part))
+            .collect();
+
+        let mut parts = Vec::new();
+        let reader = io::BufReader::with_capacity(1, Trickle(html.as_bytes()));
+        tokenize_reader(reader, |part| {
+            parts.push(format!("{:?}", part));
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(parts, expected);
+    }
+
+    #[test]
+    fn tokenize_reader_long_parts() {
+        let text = "a".repeat(1 << 20);
+        let html = format!("<p>{}</p><script>{}</scr</script>", text, text);
+
+        let mut lens = Vec::new();
+        let reader = io::BufReader::with_capacity(64, html.as_bytes());
+        tokenize_reader(reader, |part| {
+            let span = match part {
+                HTMLPart::Text(_, span) => span,
+                HTMLPart::Tag(tag) => tag.span,
+                _ => unreachable!(),
+            };
+            lens.push(span.end - span.start);
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(lens, vec![3, 1 << 20, 4, 8, (1 << 20) + 5, 9]);
+    }
+
+    #[test]
+    fn tokenize_reader_invalid_utf8() {
+        let result = tokenize_reader(&b"<p>\xff</p>"[..], |_| Ok(()));
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,464 @@
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, tag_no_case, take_till, take_while1},
+    character::complete::{char, multispace0, multispace1},
+    combinator::{all_consuming, map, opt, value},
+    multi::{many0, many1, separated_nonempty_list},
+    sequence::{delimited, preceded, tuple},
+    IResult,
+};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::tree::{Document, NodeId};
+use crate::HTMLTag;
+
+/// CSS selector
+///
+/// Supports a subset of Selectors Level 3: type, universal, class,
+/// id and attribute selectors, `:first-child`, `:last-child` and
+/// `:only-child`, descendant and child combinators, and comma
+/// separated lists.
+///
+/// Spec: https://www.w3.org/TR/selectors-3/
+///
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Selector(Vec<ComplexSelector>);
+
+/// Compound selectors joined by combinators, in source order
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct ComplexSelector {
+    first: CompoundSelector,
+    rest: Vec<(Combinator, CompoundSelector)>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Combinator {
+    Descendant,
+    Child,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct CompoundSelector {
+    name: Option<String>,
+    filters: Vec<Filter>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Filter {
+    Id(String),
+    Class(String),
+    Attribute(String, Option<(AttributeOperator, String)>),
+    FirstChild,
+    LastChild,
+    OnlyChild,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum AttributeOperator {
+    Equals,
+    Includes,
+    DashMatch,
+    Prefix,
+    Suffix,
+    Substring,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseSelectorError {
+    pub selector: String,
+    pub position: usize,
+}
+
+impl fmt::Display for ParseSelectorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Can't parse selector {:?} at column {}",
+            self.selector,
+            self.selector[..self.position].chars().count() + 1
+        )
+    }
+}
+
+impl std::error::Error for ParseSelectorError {}
+
+impl Selector {
+    pub fn parse(input: &str) -> Result<Self, ParseSelectorError> {
+        match all_consuming(delimited(multispace0, selector_list, multispace0))(input) {
+            Ok((_, selector)) => Ok(selector),
+            Err(nom::Err::Error((rest, _))) | Err(nom::Err::Failure((rest, _))) => {
+                Err(ParseSelectorError {
+                    selector: input.to_owned(),
+                    position: input.len() - rest.len(),
+                })
+            }
+            Err(nom::Err::Incomplete(_)) => Err(ParseSelectorError {
+                selector: input.to_owned(),
+                position: input.len(),
+            }),
+        }
+    }
+
+    /// Whether the selector matches a document node
+    pub fn matches(&self, document: &Document, id: NodeId) -> bool {
+        self.0.iter().any(|complex| complex.matches(document, id))
+    }
+}
+
+impl FromStr for Selector {
+    type Err = ParseSelectorError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        Selector::parse(input)
+    }
+}
+
+impl<'a> Document<'a> {
+    /// Elements matching a CSS selector, in document order
+    ///
+    /// Shorthand for parsing a `Selector` and passing it to
+    /// `select_with`.
+    ///
+    pub fn select(&self, selector: &str) -> Result<Vec<NodeId>, ParseSelectorError> {
+        Ok(self.select_with(&selector.parse()?))
+    }
+
+    /// Elements matching a CSS selector, in document order
+    pub fn select_with(&self, selector: &Selector) -> Vec<NodeId> {
+        self.iter()
+            .filter(|id| selector.matches(self, *id))
+            .collect()
+    }
+}
+
+impl ComplexSelector {
+    fn matches(&self, document: &Document, id: NodeId) -> bool {
+        let mut compounds = vec![(Combinator::Descendant, &self.first)];
+        compounds.extend(self.rest.iter().map(|(c, s)| (*c, s)));
+
+        matches_from(document, id, &compounds)
+    }
+}
+
+/// Match compounds right to left, starting from the last one
+///
+/// Each compound carries the combinator which joins it to the one
+/// before it.
+///
+fn matches_from(
+    document: &Document,
+    id: NodeId,
+    compounds: &[(Combinator, &CompoundSelector)],
+) -> bool {
+    let (last, preceding) = match compounds.split_last() {
+        Some(split) => split,
+        None => return true,
+    };
+    let (combinator, compound) = last;
+
+    if !compound.matches(document, id) {
+        return false;
+    }
+    if preceding.is_empty() {
+        return true;
+    }
+
+    match combinator {
+        Combinator::Child => document
+            .parent(id)
+            .is_some_and(|parent| matches_from(document, parent, preceding)),
+        Combinator::Descendant => document
+            .ancestors(id)
+            .any(|ancestor| matches_from(document, ancestor, preceding)),
+    }
+}
+
+impl CompoundSelector {
+    fn matches(&self, document: &Document, id: NodeId) -> bool {
+        let tag = match document[id].tag() {
+            Some(tag) => tag,
+            None => return false,
+        };
+        let name_matches = self
+            .name
+            .as_ref()
+            .is_none_or(|name| name.eq_ignore_ascii_case(&tag.name));
+
+        name_matches
+            && self
+                .filters
+                .iter()
+                .all(|filter| filter.matches(document, id, tag))
+    }
+}
+
+impl Filter {
+    fn matches(&self, document: &Document, id: NodeId, tag: &HTMLTag) -> bool {
+        match self {
+            Filter::Id(expected) => tag.attribute_value("id").as_deref() == Some(expected),
+            Filter::Class(expected) => tag
+                .attribute_value("class")
+                .is_some_and(|class| class.split_ascii_whitespace().any(|c| c == expected)),
+            Filter::Attribute(name, None) => tag
+                .attributes
+                .iter()
+                .any(|(n, _)| n.eq_ignore_ascii_case(name)),
+            Filter::Attribute(name, Some((operator, expected))) => tag
+                .attribute_value(name)
+                .is_some_and(|actual| operator.matches(&actual, expected)),
+            Filter::FirstChild => element_siblings(document, id).first() == Some(&id),
+            Filter::LastChild => element_siblings(document, id).last() == Some(&id),
+            Filter::OnlyChild => element_siblings(document, id) == [id],
+        }
+    }
+}
+
+impl AttributeOperator {
+    fn matches(self, actual: &str, expected: &str) -> bool {
+        match self {
+            AttributeOperator::Equals => actual == expected,
+            AttributeOperator::Includes => actual.split_ascii_whitespace().any(|v| v == expected),
+            AttributeOperator::DashMatch => {
+                actual == expected
+                    || actual.starts_with(expected) && actual[expected.len()..].starts_with('-')
+            }
+            AttributeOperator::Prefix => !expected.is_empty() && actual.starts_with(expected),
+            AttributeOperator::Suffix => !expected.is_empty() && actual.ends_with(expected),
+            AttributeOperator::Substring => !expected.is_empty() && actual.contains(expected),
+        }
+    }
+}
+
+/// Element children of a node's parent, including the node itself
+fn element_siblings(document: &Document, id: NodeId) -> Vec<NodeId> {
+    let siblings = match document.parent(id) {
+        Some(parent) => document.children(parent),
+        None => document.roots(),
+    };
+
+    siblings
+        .iter()
+        .copied()
+        .filter(|sibling| document[*sibling].tag().is_some())
+        .collect()
+}
+
+fn selector_list(input: &str) -> IResult<&str, Selector> {
+    map(
+        separated_nonempty_list(
+            tuple((multispace0, char(','), multispace0)),
+            complex_selector,
+        ),
+        Selector,
+    )(input)
+}
+
+fn complex_selector(input: &str) -> IResult<&str, ComplexSelector> {
+    let (input, first) = compound_selector(input)?;
+    let (input, rest) = many0(tuple((combinator, compound_selector)))(input)?;
+
+    Ok((input, ComplexSelector { first, rest }))
+}
+
+fn combinator(input: &str) -> IResult<&str, Combinator> {
+    alt((
+        value(
+            Combinator::Child,
+            tuple((multispace0, char('>'), multispace0)),
+        ),
+        value(Combinator::Descendant, multispace1),
+    ))(input)
+}
+
+fn compound_selector(input: &str) -> IResult<&str, CompoundSelector> {
+    let (input, name) = opt(type_selector)(input)?;
+    let (input, filters) = match name {
+        Some(_) => many0(filter)(input)?,
+        None => many1(filter)(input)?,
+    };
+
+    Ok((
+        input,
+        CompoundSelector {
+            name: name.flatten(),
+            filters,
+        },
+    ))
+}
+
+/// Type selector, with `None` for the universal `*`
+fn type_selector(input: &str) -> IResult<&str, Option<String>> {
+    alt((
+        value(None, char('*')),
+        map(identifier, |name| Some(name.to_owned())),
+    ))(input)
+}
+
+fn filter(input: &str) -> IResult<&str, Filter> {
+    alt((
+        map(preceded(char('#'), identifier), |id| {
+            Filter::Id(id.to_owned())
+        }),
+        map(preceded(char('.'), identifier), |class| {
+            Filter::Class(class.to_owned())
+        }),
+        attribute_filter,
+        value(Filter::FirstChild, tag_no_case(":first-child")),
+        value(Filter::LastChild, tag_no_case(":last-child")),
+        value(Filter::OnlyChild, tag_no_case(":only-child")),
+    ))(input)
+}
+
+fn attribute_filter(input: &str) -> IResult<&str, Filter> {
+    let (input, (_, _, name, _, matcher, _)) = tuple((
+        char('['),
+        multispace0,
+        identifier,
+        multispace0,
+        opt(tuple((
+            attribute_operator,
+            delimited(multispace0, attribute_value, multispace0),
+        ))),
+        char(']'),
+    ))(input)?;
+
+    Ok((
+        input,
+        Filter::Attribute(
+            name.to_owned(),
+            matcher.map(|(operator, value)| (operator, value.to_owned())),
+        ),
+    ))
+}
+
+fn attribute_operator(input: &str) -> IResult<&str, AttributeOperator> {
+    alt((
+        value(AttributeOperator::Equals, tag("=")),
+        value(AttributeOperator::Includes, tag("~=")),
+        value(AttributeOperator::DashMatch, tag("|=")),
+        value(AttributeOperator::Prefix, tag("^=")),
+        value(AttributeOperator::Suffix, tag("$=")),
+        value(AttributeOperator::Substring, tag("*=")),
+    ))(input)
+}
+
+fn attribute_value(input: &str) -> IResult<&str, &str> {
+    alt((
+        delimited(char('"'), take_till(|c| c == '"'), char('"')),
+        delimited(char('\''), take_till(|c| c == '\''), char('\'')),
+        identifier,
+    ))(input)
+}
+
+/// Identifier, without support for escapes
+///
+/// Spec: https://www.w3.org/TR/selectors-3/#lex
+///
+fn identifier(input: &str) -> IResult<&str, &str> {
+    take_while1(|c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_' || !c.is_ascii())(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format_html;
+
+    const PAGE: &str = "\
+<article>
+  <h1 id=\"title\">Title</h1>
+  <section class=\"intro wide\">
+    <h2 id=\"one\">One</h2>
+    <p>Text <a href=\"./two.html#top\" lang=\"en-US\">link</a></p>
+  </section>
+  <h2>Two</h2>
+  <h2 id=\"three\">Three</h2>
+</article>
+<h2 id=\"outside\">Outside</h2>";
+
+    fn select_text(selector: &str) -> Vec<String> {
+        let document = Document::parse(PAGE).unwrap();
+        document
+            .select(selector)
+            .unwrap()
+            .into_iter()
+            .map(|id| document.text(id))
+            .collect()
+    }
+
+    #[test]
+    fn type_selector() {
+        assert_eq!(select_text("h1"), vec!["Title"]);
+    }
+
+    #[test]
+    fn descendant_attribute_selector() {
+        assert_eq!(select_text("article h2[id]"), vec!["One", "Three"]);
+    }
+
+    #[test]
+    fn child_selector() {
+        assert_eq!(select_text("article > h2"), vec!["Two", "Three"]);
+    }
+
+    #[test]
+    fn id_and_class_selectors() {
+        assert_eq!(select_text("#one"), vec!["One"]);
+        assert_eq!(select_text(".wide > h2"), vec!["One"]);
+        assert_eq!(select_text("section.intro.wide h2"), vec!["One"]);
+        assert_eq!(select_text(".narrow"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn attribute_operators() {
+        assert_eq!(select_text("a[href^=\"./\"]"), vec!["link"]);
+        assert_eq!(select_text("a[href$='#top']"), vec!["link"]);
+        assert_eq!(select_text("a[href*=two]"), vec!["link"]);
+        assert_eq!(select_text("a[lang|=en]"), vec!["link"]);
+        assert_eq!(select_text("section[class~=intro] a"), vec!["link"]);
+        assert_eq!(select_text("h2[id=three]"), vec!["Three"]);
+    }
+
+    #[test]
+    fn structural_pseudo_classes() {
+        assert_eq!(select_text("article > :first-child"), vec!["Title"]);
+        assert_eq!(select_text("article > :last-child"), vec!["Three"]);
+        assert_eq!(select_text("p > a:only-child"), vec!["link"]);
+    }
+
+    #[test]
+    fn selector_list() {
+        assert_eq!(select_text("h1, #outside"), vec!["Title", "Outside"]);
+    }
+
+    #[test]
+    fn universal_selector() {
+        assert_eq!(select_text("section > *").len(), 2);
+    }
+
+    #[test]
+    fn selected_node_format() {
+        let document = Document::parse(PAGE).unwrap();
+        let p = document.select("section p").unwrap()[0];
+
+        assert_eq!(
+            format_html(&document.node_parts(p)),
+            "<p>Text <a href=\"./two.html#top\" lang=\"en-US\">link</a></p>"
+        );
+    }
+
+    #[test]
+    fn parse_error() {
+        assert_eq!(
+            Selector::parse("article >"),
+            Err(ParseSelectorError {
+                selector: "article >".into(),
+                position: 8,
+            })
+        );
+        assert_eq!(
+            Selector::parse("h2[id").unwrap_err().to_string(),
+            "Can't parse selector \"h2[id\" at column 3"
+        );
+    }
+}
@@ -182,4 +182,169 @@ lazy_static! {
     .iter()
     .copied()
     .collect();
+
+    /// Paragraph closing HTML elements list
+    ///
+    /// Opening any of these implies the end of an open `p` element.
+    ///
+    /// Spec: https://html.spec.whatwg.org/multipage/syntax.html#optional-tags
+    ///
+    pub static ref P_CLOSING_HTML_ELEMENTS: HashSet<&'static str> = [
+        "address",
+        "article",
+        "aside",
+        "blockquote",
+        "dd",
+        "details",
+        "dialog",
+        "div",
+        "dl",
+        "dt",
+        "fieldset",
+        "figcaption",
+        "figure",
+        "footer",
+        "form",
+        "h1",
+        "h2",
+        "h3",
+        "h4",
+        "h5",
+        "h6",
+        "header",
+        "hgroup",
+        "hr",
+        "li",
+        "main",
+        "menu",
+        "nav",
+        "ol",
+        "p",
+        "pre",
+        "section",
+        "table",
+        "ul",
+    ]
+    .iter()
+    .copied()
+    .collect();
+
+    /// Raw text HTML elements list
+    ///
+    /// Contents are text up until the matching close tag.
+    ///
+    /// Spec: https://html.spec.whatwg.org/multipage/syntax.html#raw-text-elements
+    ///
+    pub static ref RAW_TEXT_HTML_ELEMENTS: HashSet<&'static str> = [
+        "script",
+        "style",
+    ]
+    .iter()
+    .copied()
+    .collect();
+
+    /// Escapable raw text HTML elements list
+    ///
+    /// Contents are text up until the matching close tag, though
+    /// character references are still allowed.
+    ///
+    /// Spec: https://html.spec.whatwg.org/multipage/syntax.html#escapable-raw-text-elements
+    ///
+    pub static ref ESCAPABLE_RAW_TEXT_HTML_ELEMENTS: HashSet<&'static str> = [
+        "textarea",
+        "title",
+    ]
+    .iter()
+    .copied()
+    .collect();
+
+    /// Whitespace preserving HTML elements list
+    ///
+    /// Whitespace within these is significant, either as rendered
+    /// text or as code, so formatting must leave it alone.
+    ///
+    /// Spec: https://html.spec.whatwg.org/multipage/grouping-content.html#the-pre-element
+    ///
+    pub static ref WHITESPACE_PRESERVING_HTML_ELEMENTS: HashSet<&'static str> = [
+        "listing",
+        "plaintext",
+        "pre",
+        "script",
+        "style",
+        "textarea",
+        "xmp",
+    ]
+    .iter()
+    .copied()
+    .collect();
+
+    /// Block level HTML elements list
+    ///
+    /// Elements laid out as blocks, or not rendered at all, so
+    /// whitespace around them doesn't show up. Anything else, like
+    /// phrasing content, custom elements, SVG, MathML and scripts,
+    /// is taken to flow along with the surrounding text.
+    ///
+    /// Spec: https://html.spec.whatwg.org/multipage/rendering.html#the-css-user-agent-style-sheet-and-presentational-hints
+    ///
+    pub static ref BLOCK_HTML_ELEMENTS: HashSet<&'static str> = [
+        "address",
+        "article",
+        "aside",
+        "base",
+        "blockquote",
+        "body",
+        "caption",
+        "col",
+        "colgroup",
+        "dd",
+        "details",
+        "dialog",
+        "div",
+        "dl",
+        "dt",
+        "fieldset",
+        "figcaption",
+        "figure",
+        "footer",
+        "form",
+        "h1",
+        "h2",
+        "h3",
+        "h4",
+        "h5",
+        "h6",
+        "head",
+        "header",
+        "hgroup",
+        "hr",
+        "html",
+        "legend",
+        "li",
+        "link",
+        "main",
+        "menu",
+        "meta",
+        "nav",
+        "ol",
+        "optgroup",
+        "option",
+        "p",
+        "pre",
+        "search",
+        "section",
+        "summary",
+        "table",
+        "tbody",
+        "td",
+        "tfoot",
+        "th",
+        "thead",
+        "title",
+        "tr",
+        "ul",
+    ]
+    .iter()
+    .copied()
+    .collect();
 }
@@ -0,0 +1,515 @@
+use crate::parse::{parse_html, ParseHTMLError};
+use crate::standard_elements::{P_CLOSING_HTML_ELEMENTS, RAW_TEXT_HTML_ELEMENTS};
+use crate::{HTMLPart, HTMLTag, HTMLTagKind, Span};
+use std::ops::Index;
+
+/// Node handle
+///
+/// Only valid for the `Document` which created it.
+///
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct NodeId(usize);
+
+/// Document node
+///
+/// Elements are represented by their open or void tag, with the
+/// close tag kept alongside. Text, comments and doctypes have no
+/// children.
+///
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Node<'a> {
+    pub part: HTMLPart<'a>,
+    pub close: Option<HTMLTag<'a>>,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+}
+
+impl<'a> Node<'a> {
+    fn new(part: HTMLPart<'a>, parent: Option<NodeId>) -> Self {
+        Node {
+            part,
+            close: None,
+            parent,
+            children: Vec::new(),
+        }
+    }
+
+    /// Open or void tag, if this node is an element
+    pub fn tag(&self) -> Option<&HTMLTag<'a>> {
+        match &self.part {
+            HTMLPart::Tag(tag) if tag.kind != HTMLTagKind::Close => Some(tag),
+            _ => None,
+        }
+    }
+
+    /// Element name, if this node is an element
+    pub fn name(&self) -> Option<&str> {
+        self.tag().map(|tag| tag.name.as_ref())
+    }
+
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+
+    /// Copy anything borrowed from the input, so the node can outlive it
+    pub fn into_owned(self) -> Node<'static> {
+        Node {
+            part: self.part.into_owned(),
+            close: self.close.map(HTMLTag::into_owned),
+            parent: self.parent,
+            children: self.children,
+        }
+    }
+}
+
+/// HTML document tree
+///
+/// Built from the flat `HTMLPart` stream, closing elements
+/// whose end tags are optional when the next tag implies it.
+/// Stray close tags without a matching open element are kept
+/// as childless nodes so nothing from the input is dropped.
+///
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Document<'a> {
+    nodes: Vec<Node<'a>>,
+    roots: Vec<NodeId>,
+}
+
+impl<'a> Document<'a> {
+    pub fn parse(input: &'a str) -> Result<Self, ParseHTMLError> {
+        parse_html(input).map(Document::from)
+    }
+
+    /// Copy anything borrowed from the input, so the document can
+    /// outlive it, or be sent to another task
+    ///
+    /// Node handles stay valid.
+    ///
+    pub fn into_owned(self) -> Document<'static> {
+        Document {
+            nodes: self.nodes.into_iter().map(Node::into_owned).collect(),
+            roots: self.roots,
+        }
+    }
+
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self[id].parent
+    }
+
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        &self[id].children
+    }
+
+    /// Ancestors from the parent up to the root
+    pub fn ancestors(&self, id: NodeId) -> Ancestors<'_, 'a> {
+        Ancestors {
+            document: self,
+            next: self.parent(id),
+        }
+    }
+
+    /// Descendants in document order, excluding the node itself
+    pub fn descendants(&self, id: NodeId) -> Descendants<'_, 'a> {
+        Descendants {
+            document: self,
+            stack: self.children(id).iter().rev().copied().collect(),
+        }
+    }
+
+    /// All nodes in document order
+    pub fn iter(&self) -> Descendants<'_, 'a> {
+        Descendants {
+            document: self,
+            stack: self.roots.iter().rev().copied().collect(),
+        }
+    }
+
+    /// Flatten back to parts, ready for `format_html`
+    ///
+    /// Implied close tags are written out explicitly.
+    ///
+    pub fn to_parts(&self) -> Vec<HTMLPart<'a>> {
+        let mut parts = Vec::with_capacity(self.nodes.len());
+        for id in &self.roots {
+            self.push_parts(*id, &mut parts);
+        }
+
+        parts
+    }
+
+    /// Source span of a node, from its opening part to its close tag
+    ///
+    /// Elements with an implied close end with their last child.
+    ///
+    pub fn span(&self, id: NodeId) -> Span {
+        let node = &self[id];
+        let start = node.part.span();
+        let end = match &node.close {
+            Some(close) => close.span,
+            None => self
+                .children(id)
+                .last()
+                .map_or(start, |child| self.span(*child)),
+        };
+
+        Span::new(start.start, end.end.max(start.end))
+    }
+
+    /// Decoded text content of a node and its descendants
+    ///
+    /// `script` and `style` contents are raw text, so are left as is.
+    ///
+    pub fn text(&self, id: NodeId) -> String {
+        std::iter::once(id)
+            .chain(self.descendants(id))
+            .filter_map(|id| match &self[id].part {
+                HTMLPart::Text(text, _) if self.is_raw_text(id) => Some(text.clone()),
+                HTMLPart::CData(text, _) => Some(text.clone()),
+                part => part.decoded_text().map(|text| text.into_owned().into()),
+            })
+            .collect()
+    }
+
+    fn is_raw_text(&self, id: NodeId) -> bool {
+        self.parent(id)
+            .and_then(|parent| self[parent].name())
+            .is_some_and(|name| RAW_TEXT_HTML_ELEMENTS.contains(name.to_ascii_lowercase().as_str()))
+    }
+
+    /// Flatten a single node and its descendants back to parts
+    pub fn node_parts(&self, id: NodeId) -> Vec<HTMLPart<'a>> {
+        let mut parts = Vec::new();
+        self.push_parts(id, &mut parts);
+
+        parts
+    }
+
+    fn push_parts(&self, id: NodeId, parts: &mut Vec<HTMLPart<'a>>) {
+        let node = &self[id];
+        parts.push(node.part.clone());
+
+        if let Some(HTMLTag {
+            kind: HTMLTagKind::Open,
+            name,
+            ..
+        }) = node.tag()
+        {
+            for child in &node.children {
+                self.push_parts(*child, parts);
+            }
+
+            let close = node.close.clone().unwrap_or(HTMLTag {
+                kind: HTMLTagKind::Close,
+                name: name.clone(),
+                attributes: Vec::new(),
+                span: Span::default(),
+            });
+            parts.push(HTMLPart::Tag(close));
+        }
+    }
+
+    fn push_node(&mut self, part: HTMLPart<'a>, parent: Option<NodeId>) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(Node::new(part, parent));
+
+        match parent {
+            Some(parent_id) => self.nodes[parent_id.0].children.push(id),
+            None => self.roots.push(id),
+        }
+
+        id
+    }
+}
+
+impl<'a> From<Vec<HTMLPart<'a>>> for Document<'a> {
+    fn from(parts: Vec<HTMLPart<'a>>) -> Self {
+        let mut document = Document {
+            nodes: Vec::with_capacity(parts.len()),
+            roots: Vec::new(),
+        };
+        let mut open: Vec<NodeId> = Vec::new();
+
+        for part in parts {
+            match &part {
+                HTMLPart::Tag(tag) if tag.kind == HTMLTagKind::Close => {
+                    let matching = open.iter().rposition(|id| {
+                        let name = document[*id].name().unwrap_or("");
+                        name.eq_ignore_ascii_case(&tag.name)
+                    });
+
+                    match matching {
+                        Some(position) => {
+                            let id = open[position];
+                            open.truncate(position);
+                            if let HTMLPart::Tag(close) = part {
+                                document.nodes[id.0].close = Some(close);
+                            }
+                        }
+                        None => {
+                            document.push_node(part, open.last().copied());
+                        }
+                    }
+                }
+                HTMLPart::Tag(tag) => {
+                    while let Some(current) = open.last() {
+                        let current_name = document[*current].name().unwrap_or("");
+                        if !closes_implicitly(current_name, &tag.name) {
+                            break;
+                        }
+                        open.pop();
+                    }
+
+                    let is_open = tag.kind == HTMLTagKind::Open;
+                    let id = document.push_node(part, open.last().copied());
+                    if is_open {
+                        open.push(id);
+                    }
+                }
+                _ => {
+                    document.push_node(part, open.last().copied());
+                }
+            }
+        }
+
+        document
+    }
+}
+
+impl<'a> Index<NodeId> for Document<'a> {
+    type Output = Node<'a>;
+
+    fn index(&self, id: NodeId) -> &Self::Output {
+        &self.nodes[id.0]
+    }
+}
+
+pub struct Ancestors<'d, 'a> {
+    document: &'d Document<'a>,
+    next: Option<NodeId>,
+}
+
+impl<'d, 'a> Iterator for Ancestors<'d, 'a> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let id = self.next?;
+        self.next = self.document.parent(id);
+
+        Some(id)
+    }
+}
+
+pub struct Descendants<'d, 'a> {
+    document: &'d Document<'a>,
+    stack: Vec<NodeId>,
+}
+
+impl<'d, 'a> Iterator for Descendants<'d, 'a> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let id = self.stack.pop()?;
+        self.stack
+            .extend(self.document.children(id).iter().rev().copied());
+
+        Some(id)
+    }
+}
+
+/// Whether opening `next` implies the end of an open `current` element
+///
+/// Spec: https://html.spec.whatwg.org/multipage/syntax.html#optional-tags
+///
+pub(crate) fn closes_implicitly(current: &str, next: &str) -> bool {
+    let current = current.to_ascii_lowercase();
+    let next = next.to_ascii_lowercase();
+
+    match current.as_str() {
+        "p" => P_CLOSING_HTML_ELEMENTS.contains(next.as_str()),
+        "li" => next == "li",
+        "dt" | "dd" => matches!(next.as_str(), "dt" | "dd"),
+        "td" | "th" => matches!(
+            next.as_str(),
+            "td" | "th" | "tr" | "thead" | "tbody" | "tfoot"
+        ),
+        "tr" => matches!(next.as_str(), "tr" | "thead" | "tbody" | "tfoot"),
+        "thead" | "tbody" => matches!(next.as_str(), "tbody" | "tfoot"),
+        "option" => matches!(next.as_str(), "option" | "optgroup"),
+        "optgroup" => next == "optgroup",
+        "rt" | "rp" => matches!(next.as_str(), "rt" | "rp"),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format_html;
+
+    fn names<'a>(document: &'a Document, ids: &[NodeId]) -> Vec<&'a str> {
+        ids.iter()
+            .map(|id| document[*id].name().unwrap_or("#text"))
+            .collect()
+    }
+
+    #[test]
+    fn nested_elements() {
+        let document = Document::parse("<div><p>Some <em>text</em></p></div>").unwrap();
+        let div = document.roots()[0];
+        let p = document.children(div)[0];
+
+        assert_eq!(names(&document, document.roots()), vec!["div"]);
+        assert_eq!(names(&document, document.children(p)), vec!["#text", "em"]);
+        assert_eq!(document.parent(p), Some(div));
+        assert_eq!(document.parent(div), None);
+    }
+
+    #[test]
+    fn implied_paragraph_close() {
+        let document = Document::parse("<p>One<p>Two<div>Three</div>").unwrap();
+
+        assert_eq!(names(&document, document.roots()), vec!["p", "p", "div"]);
+        assert_eq!(document[document.roots()[0]].close, None);
+    }
+
+    #[test]
+    fn implied_list_item_close() {
+        let document = Document::parse("<ul><li>One<li>Two</ul>").unwrap();
+        let ul = document.roots()[0];
+
+        assert_eq!(names(&document, document.children(ul)), vec!["li", "li"]);
+        assert!(document[ul].close.is_some());
+    }
+
+    #[test]
+    fn implied_table_cell_close() {
+        let document = Document::parse("<table><tr><td>A<td>B<tr><td>C</table>").unwrap();
+        let table = document.roots()[0];
+        let rows = document.children(table);
+
+        assert_eq!(names(&document, rows), vec!["tr", "tr"]);
+        assert_eq!(
+            names(&document, document.children(rows[0])),
+            vec!["td", "td"]
+        );
+        assert_eq!(names(&document, document.children(rows[1])), vec!["td"]);
+    }
+
+    #[test]
+    fn stray_close_tag() {
+        let document = Document::parse("<div></span></div>").unwrap();
+        let div = document.roots()[0];
+        let stray = document.children(div)[0];
+
+        assert_eq!(
+            document[stray].part,
+            HTMLPart::Tag(HTMLTag {
+                kind: HTMLTagKind::Close,
+                name: "span".into(),
+                attributes: Vec::new(),
+                span: Span::new(5, 12),
+            })
+        );
+        assert!(document[div].close.is_some());
+    }
+
+    #[test]
+    fn ancestors_and_descendants() {
+        let document = Document::parse("<main><ul><li><a>Link</a></li></ul></main>").unwrap();
+        let main = document.roots()[0];
+        let a = document
+            .iter()
+            .find(|id| document[*id].name() == Some("a"))
+            .unwrap();
+        let ancestors: Vec<NodeId> = document.ancestors(a).collect();
+        let descendants: Vec<NodeId> = document.descendants(main).collect();
+
+        assert_eq!(names(&document, &ancestors), vec!["li", "ul", "main"]);
+        assert_eq!(
+            names(&document, &descendants),
+            vec!["ul", "li", "a", "#text"]
+        );
+    }
+
+    #[test]
+    fn round_trip_format() {
+        let document = Document::parse("<ul><li>One<li>Two</ul>").unwrap();
+
+        assert_eq!(
+            format_html(&document.to_parts()),
+            "\
+<ul>
+  <li>One</li>
+  <li>Two</li>
+</ul>"
+        );
+    }
+
+    #[test]
+    fn node_round_trip_format() {
+        let document = Document::parse("<div><p>Some <em>text</em></p></div>").unwrap();
+        let p = document.children(document.roots()[0])[0];
+
+        assert_eq!(
+            format_html(&document.node_parts(p)),
+            "<p>Some <em>text</em></p>"
+        );
+    }
+
+    #[test]
+    fn into_owned_outlives_input() {
+        let (document, id) = {
+            let input = String::from("<ul><li>One<li>Two</ul>");
+            let document = Document::parse(&input).unwrap();
+            let id = document.select("li:last-child").unwrap()[0];
+
+            (document.into_owned(), id)
+        };
+
+        assert_eq!(document.text(id), "Two");
+        assert_eq!(
+            format_html(&document.to_parts()),
+            "<ul>\n  <li>One</li>\n  <li>Two</li>\n</ul>"
+        );
+    }
+
+    #[test]
+    fn text_content_cdata() {
+        let document = Document::parse("<svg><text><![CDATA[a < b]]> &lt; c</text></svg>").unwrap();
+
+        assert_eq!(document.text(document.roots()[0]), "a < b < c");
+    }
+
+    #[test]
+    fn text_content() {
+        let document = Document::parse(
+            "<h2 id=\"a\">Fish &amp; <em>chips</em><script>a &amp;&amp; b</script></h2>",
+        )
+        .unwrap();
+
+        assert_eq!(
+            document.text(document.roots()[0]),
+            "Fish & chipsa &amp;&amp; b"
+        );
+    }
+
+    #[test]
+    fn node_spans() {
+        let input = "<ul><li>One<li><b>Two</b></ul>";
+        let document = Document::parse(input).unwrap();
+        let ul = document.roots()[0];
+        let items = document.children(ul);
+
+        assert_eq!(document.span(ul), Span::new(0, 30));
+        assert_eq!(document.span(items[0]), Span::new(4, 11));
+        assert_eq!(document.span(items[1]), Span::new(11, 25));
+    }
+}
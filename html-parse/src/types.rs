@@ -1,7 +1,38 @@
 use crate::depth::DepthChange;
+use crate::escape::{decode_attribute_value, decode_text};
 use std::borrow::Cow;
 
-#[derive(Debug, Eq, PartialEq)]
+/// Byte range of a part within the parsed input
+///
+/// Parts which weren't parsed, like embed results, have an
+/// empty default span.
+///
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Move the span forward by a number of bytes
+    pub fn offset(self, by: usize) -> Self {
+        Span::new(self.start + by, self.end + by)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum HTMLTagKind {
     Open,
     Close,
@@ -18,11 +49,40 @@ impl DepthChange for HTMLTagKind {
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct HTMLTag<'a> {
     pub kind: HTMLTagKind,
-    pub name: &'a str,
-    pub attributes: Vec<(&'a str, Option<&'a str>)>,
+    pub name: Cow<'a, str>,
+    pub attributes: Vec<(Cow<'a, str>, Option<Cow<'a, str>>)>,
+    pub span: Span,
+}
+
+impl<'a> HTMLTag<'a> {
+    /// Decoded value of an attribute
+    ///
+    /// `None` when the attribute is missing or has no value.
+    ///
+    pub fn attribute_value(&self, name: &str) -> Option<Cow<'_, str>> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .and_then(|(_, value)| value.as_deref())
+            .map(decode_attribute_value)
+    }
+
+    /// Copy anything borrowed from the input, so the tag can outlive it
+    pub fn into_owned(self) -> HTMLTag<'static> {
+        HTMLTag {
+            kind: self.kind,
+            name: into_owned(self.name),
+            attributes: self
+                .attributes
+                .into_iter()
+                .map(|(name, value)| (into_owned(name), value.map(into_owned)))
+                .collect(),
+            span: self.span,
+        }
+    }
 }
 
 impl<'a> DepthChange for HTMLTag<'a> {
@@ -31,12 +91,76 @@ impl<'a> DepthChange for HTMLTag<'a> {
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum HTMLPart<'a> {
-    Comment(&'a str),
-    DocType,
+    /// Input which couldn't be parsed, kept verbatim
+    Bogus(Cow<'a, str>, Span),
+    CData(Cow<'a, str>, Span),
+    Comment(Cow<'a, str>, Span),
+    /// Contents following the `DOCTYPE` keyword
+    DocType(Cow<'a, str>, Span),
+    ProcessingInstruction(Cow<'a, str>, Span),
     Tag(HTMLTag<'a>),
-    Text(Cow<'a, str>),
+    Text(Cow<'a, str>, Span),
+}
+
+impl<'a> HTMLPart<'a> {
+    /// Decoded text, if this is a text part
+    ///
+    /// Not meant for the contents of `script` and `style`, which
+    /// are raw text without character references.
+    ///
+    pub fn decoded_text(&self) -> Option<Cow<'_, str>> {
+        match self {
+            HTMLPart::Text(text, _) => Some(decode_text(text)),
+            _ => None,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            HTMLPart::Bogus(_, span) => *span,
+            HTMLPart::CData(_, span) => *span,
+            HTMLPart::Comment(_, span) => *span,
+            HTMLPart::DocType(_, span) => *span,
+            HTMLPart::ProcessingInstruction(_, span) => *span,
+            HTMLPart::Tag(tag) => tag.span,
+            HTMLPart::Text(_, span) => *span,
+        }
+    }
+
+    /// Move the part's span forward by a number of bytes
+    pub fn offset_span(self, by: usize) -> Self {
+        match self {
+            HTMLPart::Bogus(bogus, span) => HTMLPart::Bogus(bogus, span.offset(by)),
+            HTMLPart::Comment(comment, span) => HTMLPart::Comment(comment, span.offset(by)),
+            HTMLPart::CData(text, span) => HTMLPart::CData(text, span.offset(by)),
+            HTMLPart::DocType(doctype, span) => HTMLPart::DocType(doctype, span.offset(by)),
+            HTMLPart::ProcessingInstruction(instruction, span) => {
+                HTMLPart::ProcessingInstruction(instruction, span.offset(by))
+            }
+            HTMLPart::Tag(tag) => HTMLPart::Tag(HTMLTag {
+                span: tag.span.offset(by),
+                ..tag
+            }),
+            HTMLPart::Text(text, span) => HTMLPart::Text(text, span.offset(by)),
+        }
+    }
+
+    /// Copy anything borrowed from the input, so the part can outlive it
+    pub fn into_owned(self) -> HTMLPart<'static> {
+        match self {
+            HTMLPart::Bogus(bogus, span) => HTMLPart::Bogus(into_owned(bogus), span),
+            HTMLPart::CData(text, span) => HTMLPart::CData(into_owned(text), span),
+            HTMLPart::Comment(comment, span) => HTMLPart::Comment(into_owned(comment), span),
+            HTMLPart::DocType(doctype, span) => HTMLPart::DocType(into_owned(doctype), span),
+            HTMLPart::ProcessingInstruction(instruction, span) => {
+                HTMLPart::ProcessingInstruction(into_owned(instruction), span)
+            }
+            HTMLPart::Tag(tag) => HTMLPart::Tag(tag.into_owned()),
+            HTMLPart::Text(text, span) => HTMLPart::Text(into_owned(text), span),
+        }
+    }
 }
 
 impl<'a> DepthChange for HTMLPart<'a> {
@@ -47,3 +171,51 @@ impl<'a> DepthChange for HTMLPart<'a> {
         }
     }
 }
+
+/// Own a string, keeping it as is if it's already owned
+pub(crate) fn into_owned(string: Cow<'_, str>) -> Cow<'static, str> {
+    Cow::Owned(string.into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attribute_value_decoded() {
+        let tag = HTMLTag {
+            kind: HTMLTagKind::Open,
+            name: "a".into(),
+            attributes: vec![
+                ("href".into(), Some("?a=1&amp;b=2".into())),
+                ("hidden".into(), None),
+            ],
+            span: Span::default(),
+        };
+
+        assert_eq!(tag.attribute_value("HREF"), Some("?a=1&b=2".into()));
+        assert_eq!(tag.attribute_value("hidden"), None);
+        assert_eq!(tag.attribute_value("title"), None);
+    }
+
+    #[test]
+    fn text_decoded() {
+        let part = HTMLPart::Text("&lt;p&gt;".into(), Span::default());
+
+        assert_eq!(part.decoded_text(), Some("<p>".into()));
+    }
+
+    #[test]
+    fn part_into_owned() {
+        let part = {
+            let input = String::from("<a href='/'>");
+            crate::parse_html(&input).unwrap().remove(0).into_owned()
+        };
+
+        assert!(matches!(
+            part,
+            HTMLPart::Tag(HTMLTag { ref name, ref attributes, .. })
+                if name == "a" && attributes == &[("href".into(), Some("/".into()))]
+        ));
+    }
+}